@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use openhousepopulator::bench::HouseNumberMatching;
+use openhousepopulator::Config;
+use osmpbfreader::{Node, NodeId, OsmId, OsmObj, Tags, Way, WayId};
+
+const CELL_SIZE: f64 = 0.0002;
+
+fn tags(pairs: &[(&str, &str)]) -> Tags {
+    pairs
+        .iter()
+        .map(|(key, value)| ((*key).into(), (*value).into()))
+        .collect()
+}
+
+fn node(id: i64, lon: f64, lat: f64, tags: Tags) -> OsmObj {
+    OsmObj::Node(Node {
+        id: NodeId(id),
+        tags,
+        decimicro_lat: (lat * 10000000.) as i32,
+        decimicro_lon: (lon * 10000000.) as i32,
+    })
+}
+
+/// Generates a grid of `size` x `size` apartment buildings, each with an address node inside,
+/// and an industrial landuse area covering every tenth row
+fn generate_objs(size: i64) -> BTreeMap<OsmId, OsmObj> {
+    let mut objs = BTreeMap::new();
+    let mut next_id = 1;
+    let mut insert = |obj: OsmObj| {
+        objs.insert(obj.id(), obj);
+    };
+
+    for x in 0..size {
+        for y in 0..size {
+            let (lon, lat) = (x as f64 * CELL_SIZE, y as f64 * CELL_SIZE);
+            let corners = [
+                (lon, lat),
+                (lon + CELL_SIZE * 0.8, lat),
+                (lon + CELL_SIZE * 0.8, lat + CELL_SIZE * 0.8),
+                (lon, lat + CELL_SIZE * 0.8),
+            ];
+            let mut nodes = vec![];
+            for (lon, lat) in corners {
+                insert(node(next_id, lon, lat, Tags::new()));
+                nodes.push(NodeId(next_id));
+                next_id += 1;
+            }
+            nodes.push(nodes[0]);
+            insert(OsmObj::Way(Way {
+                id: WayId(next_id),
+                tags: tags(&[("building", "apartments")]),
+                nodes,
+            }));
+            next_id += 1;
+
+            insert(node(
+                next_id,
+                lon + CELL_SIZE * 0.4,
+                lat + CELL_SIZE * 0.4,
+                tags(&[("addr:housenumber", "1-4")]),
+            ));
+            next_id += 1;
+        }
+    }
+
+    for row in (0..size).step_by(10) {
+        let lat = row as f64 * CELL_SIZE - CELL_SIZE * 0.1;
+        let corners = [
+            (-CELL_SIZE, lat),
+            (size as f64 * CELL_SIZE, lat),
+            (size as f64 * CELL_SIZE, lat + CELL_SIZE),
+            (-CELL_SIZE, lat + CELL_SIZE),
+        ];
+        let mut nodes = vec![];
+        for (lon, lat) in corners {
+            insert(node(next_id, lon, lat, Tags::new()));
+            nodes.push(NodeId(next_id));
+            next_id += 1;
+        }
+        nodes.push(nodes[0]);
+        insert(OsmObj::Way(Way {
+            id: WayId(next_id),
+            tags: tags(&[("landuse", "industrial")]),
+            nodes,
+        }));
+        next_id += 1;
+    }
+
+    objs
+}
+
+fn bench_house_number_matching(c: &mut Criterion) {
+    let config = Config::default();
+    let mut group = c.benchmark_group("house_number_matching");
+    group.sample_size(10);
+    for size in [20, 40, 80] {
+        let matching = HouseNumberMatching::new(&generate_objs(size), &config).unwrap();
+        let buildings = size * size;
+        // Both implementations have to find every address node in its building
+        assert_eq!(matching.indexed(), matching.brute_force());
+        assert_eq!(matching.indexed(), buildings as usize);
+        group.bench_with_input(
+            BenchmarkId::new("brute_force", buildings),
+            &matching,
+            |b, matching| b.iter(|| matching.brute_force()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("rtree", buildings),
+            &matching,
+            |b, matching| b.iter(|| matching.indexed()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_house_number_matching);
+criterion_main!(benches);
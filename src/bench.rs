@@ -0,0 +1,66 @@
+//! Internals exposed for the benchmarks only, not part of the public api
+
+use geo::Point;
+use osmpbfreader::{OsmId, OsmObj};
+use std::collections::BTreeMap;
+
+use crate::index::SpatialIndex;
+use crate::pbf::{is_building, load_housenumbers, load_ways, GenericGeometry};
+use crate::report::DataQualityReport;
+use crate::{Config, Error};
+
+/// Building geometries and house number points loaded once, to compare matching them with and without index
+pub struct HouseNumberMatching {
+    buildings: Vec<GenericGeometry>,
+    points: Vec<Point>,
+    index: SpatialIndex,
+}
+
+impl HouseNumberMatching {
+    pub fn new(osm_objs: &BTreeMap<OsmId, OsmObj>, config: &Config) -> Result<Self, Error> {
+        let mut report = DataQualityReport::default();
+        let buildings = load_ways(osm_objs, is_building, config, &mut report)?
+            .into_iter()
+            .map(|way| way.geometry)
+            .collect();
+        let house_numbers = load_housenumbers(osm_objs, config, &mut report)?;
+        let points = house_numbers
+            .iter()
+            .map(|house_number| house_number.point)
+            .collect();
+        let index = SpatialIndex::new(
+            house_numbers,
+            vec![],
+            vec![],
+            BTreeMap::new(),
+            vec![],
+            vec![],
+        );
+        Ok(HouseNumberMatching {
+            buildings,
+            points,
+            index,
+        })
+    }
+
+    /// Counts the house number points inside all buildings using the spatial index
+    pub fn indexed(&self) -> usize {
+        self.buildings
+            .iter()
+            .map(|building| self.index.house_numbers_in(building).count())
+            .sum()
+    }
+
+    /// Counts the house number points inside all buildings by testing every point against every building
+    pub fn brute_force(&self) -> usize {
+        self.buildings
+            .iter()
+            .map(|building| {
+                self.points
+                    .iter()
+                    .filter(|point| building.contains_point(point))
+                    .count()
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,308 @@
+use osmpbfreader::Tags;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::pbf::BuildingClass;
+
+/// Condition on a single tag. Without any constraint the tag only has to be present.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TagCondition {
+    pub key: String,
+    /// Tag value has to be one of these values
+    #[serde(default)]
+    pub values: Option<Vec<String>>,
+    /// Tag has to be present (`true`) or absent (`false`)
+    #[serde(default)]
+    pub present: Option<bool>,
+    /// Numeric tag value has to be greater than
+    #[serde(default)]
+    pub gt: Option<f64>,
+    /// Numeric tag value has to be greater than or equal to
+    #[serde(default)]
+    pub ge: Option<f64>,
+    /// Numeric tag value has to be less than
+    #[serde(default)]
+    pub lt: Option<f64>,
+    /// Numeric tag value has to be less than or equal to
+    #[serde(default)]
+    pub le: Option<f64>,
+}
+
+impl TagCondition {
+    pub fn key(key: &str) -> TagCondition {
+        TagCondition {
+            key: key.to_string(),
+            values: None,
+            present: None,
+            gt: None,
+            ge: None,
+            lt: None,
+            le: None,
+        }
+    }
+
+    pub fn values(key: &str, values: &[&str]) -> TagCondition {
+        TagCondition {
+            values: Some(values.iter().map(|value| value.to_string()).collect()),
+            ..TagCondition::key(key)
+        }
+    }
+
+    fn matches(&self, tags: &Tags) -> bool {
+        let value = tags.get(self.key.as_str());
+        // Absent tags have no value to constrain, present ones are checked further below
+        if self.present == Some(false) {
+            return value.is_none();
+        }
+        let Some(value) = value else {
+            return false;
+        };
+        if let Some(values) = &self.values {
+            if !values.iter().any(|allowed| allowed == value) {
+                return false;
+            }
+        }
+
+        let comparisons = [
+            (self.gt, f64::gt as fn(&f64, &f64) -> bool),
+            (self.ge, f64::ge),
+            (self.lt, f64::lt),
+            (self.le, f64::le),
+        ];
+        if comparisons.iter().all(|(bound, _)| bound.is_none()) {
+            return true;
+        }
+        let Ok(number) = value.trim().parse::<f64>() else {
+            return false;
+        };
+        comparisons
+            .iter()
+            .all(|(bound, compare)| bound.is_none_or(|bound| compare(&number, &bound)))
+    }
+}
+
+/// Formula deciding about the base flat count of a building matching a rule
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlatFormula {
+    /// Fixed number of flats
+    Fixed(usize),
+    /// House numbers multiplied by the factor (`housenumber_factor` if not set), `default` flats without house numbers
+    HouseNumbers {
+        #[serde(default)]
+        factor: Option<usize>,
+        #[serde(default)]
+        default: usize,
+    },
+}
+
+/// Rule classifying buildings whose tags match all conditions
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ClassificationRule {
+    pub name: String,
+    #[serde(rename = "match", default)]
+    pub conditions: Vec<TagCondition>,
+    pub class: BuildingClass,
+    pub flats: FlatFormula,
+    /// If the flat count is multiplied by the building levels
+    #[serde(default = "default_levels")]
+    pub levels: bool,
+}
+
+fn default_levels() -> bool {
+    true
+}
+
+impl ClassificationRule {
+    pub fn new(
+        name: &str,
+        conditions: Vec<TagCondition>,
+        class: BuildingClass,
+        flats: FlatFormula,
+    ) -> ClassificationRule {
+        ClassificationRule {
+            name: name.to_string(),
+            conditions,
+            class,
+            flats,
+            levels: true,
+        }
+    }
+
+    pub(crate) fn matches(&self, tags: &Tags) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(tags))
+    }
+
+    /// Rules reproducing the former single home, apartment and unspecified building lists
+    pub(crate) fn defaults() -> Vec<ClassificationRule> {
+        vec![
+            ClassificationRule {
+                levels: false,
+                ..ClassificationRule::new(
+                    "single home",
+                    vec![TagCondition::values("building", &["house", "detached"])],
+                    BuildingClass::SingleHome,
+                    FlatFormula::Fixed(1),
+                )
+            },
+            ClassificationRule::new(
+                "apartment",
+                vec![TagCondition::values(
+                    "building",
+                    &["apartments", "residential"],
+                )],
+                BuildingClass::Apartment,
+                FlatFormula::HouseNumbers {
+                    factor: None,
+                    default: 4,
+                },
+            ),
+            ClassificationRule::new(
+                "unspecified",
+                vec![TagCondition::values(
+                    "building",
+                    &["terrace", "semidetached_house"],
+                )],
+                BuildingClass::Unspecified,
+                FlatFormula::HouseNumbers {
+                    factor: None,
+                    default: 4,
+                },
+            ),
+            ClassificationRule::new(
+                "building=yes with house numbers",
+                vec![TagCondition::values("building", &["yes"])],
+                BuildingClass::Other,
+                FlatFormula::HouseNumbers {
+                    factor: Some(1),
+                    default: 0,
+                },
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).into(), (*value).into()))
+            .collect()
+    }
+
+    fn levels(constrain: impl Fn(&mut TagCondition)) -> TagCondition {
+        let mut condition = TagCondition::key("building:levels");
+        constrain(&mut condition);
+        condition
+    }
+
+    #[test]
+    fn matches_tag_conditions() {
+        let cases = [
+            // Presence
+            (TagCondition::key("building:levels"), "3", true),
+            (levels(|c| c.present = Some(true)), "3", true),
+            (levels(|c| c.present = Some(false)), "3", false),
+            // Operators on numbers
+            (levels(|c| c.gt = Some(2.0)), "3", true),
+            (levels(|c| c.gt = Some(3.0)), "3", false),
+            (levels(|c| c.ge = Some(3.0)), "3", true),
+            (levels(|c| c.ge = Some(3.5)), "3", false),
+            (levels(|c| c.lt = Some(4.0)), "3", true),
+            (levels(|c| c.lt = Some(3.0)), "3", false),
+            (levels(|c| c.le = Some(3.0)), "3", true),
+            (levels(|c| c.le = Some(2.5)), "3", false),
+            (levels(|c| c.gt = Some(2.0)), " 2.5 ", true),
+            // Non-numeric values never satisfy comparisons
+            (levels(|c| c.gt = Some(2.0)), "many", false),
+            (levels(|c| c.le = Some(2.0)), "2;3", false),
+            (levels(|c| c.lt = Some(2.0)), "", false),
+            // Value sets
+            (
+                TagCondition::values("building:levels", &["2", "3"]),
+                "3",
+                true,
+            ),
+            (
+                TagCondition::values("building:levels", &["2", "3"]),
+                "4",
+                false,
+            ),
+            // Combined constraints all have to hold
+            (
+                levels(|c| {
+                    c.ge = Some(2.0);
+                    c.lt = Some(5.0);
+                }),
+                "4",
+                true,
+            ),
+            (
+                levels(|c| {
+                    c.ge = Some(2.0);
+                    c.lt = Some(5.0);
+                }),
+                "5",
+                false,
+            ),
+            (
+                levels(|c| {
+                    c.present = Some(true);
+                    c.gt = Some(3.0);
+                }),
+                "3",
+                false,
+            ),
+            (
+                levels(|c| {
+                    c.present = Some(true);
+                    c.values = Some(vec!["3".to_string()]);
+                }),
+                "2",
+                false,
+            ),
+            (
+                levels(|c| {
+                    c.values = Some(vec!["1".to_string(), "3".to_string()]);
+                    c.ge = Some(2.0);
+                }),
+                "1",
+                false,
+            ),
+            (
+                levels(|c| {
+                    c.values = Some(vec!["1".to_string(), "3".to_string()]);
+                    c.ge = Some(2.0);
+                }),
+                "3",
+                true,
+            ),
+        ];
+        for (condition, value, expected) in cases {
+            assert_eq!(
+                condition.matches(&tags(&[("building:levels", value)])),
+                expected,
+                "{condition:?} on {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn matches_absent_tags_only_by_absence() {
+        let no_levels = tags(&[("building", "house")]);
+        assert!(levels(|c| c.present = Some(false)).matches(&no_levels));
+        for condition in [
+            TagCondition::key("building:levels"),
+            levels(|c| c.present = Some(true)),
+            levels(|c| c.lt = Some(3.0)),
+            TagCondition::values("building:levels", &["1"]),
+        ] {
+            assert!(!condition.matches(&no_levels), "{condition:?}");
+        }
+    }
+}
@@ -1,4 +1,41 @@
+use osmpbfreader::Tags;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::collections::BTreeMap;
+
+use crate::classification::ClassificationRule;
+use crate::household::HouseholdDistribution;
+use crate::parser::housenumber::Locale;
+use crate::parser::housenumber::RangeMode;
+use crate::pbf::BuildingClass;
+use crate::Error;
+
+/// Strategy to estimate the flat count of buildings without `building:flats`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlatEstimator {
+    /// Estimates flats by building type and house numbers
+    #[default]
+    Tags,
+    /// Estimates flats by footprint area for building classes with a configured area per flat
+    Area,
+    /// Estimates flats by gross floor area (footprint × habitable storeys) for building classes with a configured floor area per flat
+    FloorArea,
+}
+
+/// Strategy to distribute a known population to buildings
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DistributionMode {
+    /// Adds inhabitants one by one to random flats, rerolling crowded flats
+    #[default]
+    Random,
+    /// Allocates inhabitants proportionally to the flat count of each building (largest remainder)
+    Proportional,
+}
+
 #[derive(Clone, serde::Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub(crate) reroll_threshold: u64,
     pub(crate) reroll_probability: i32,
@@ -6,15 +43,73 @@ pub struct Config {
     pub(crate) housenumber_factor: usize,
     pub(crate) exclude_landuse: Vec<String>,
     pub(crate) exclude_tags: Vec<String>,
-    pub(crate) single_home_list: Vec<String>,
-    pub(crate) apartment_list: Vec<String>,
-    pub(crate) unspecified_list: Vec<String>,
+    pub(crate) classification_rules: Vec<ClassificationRule>,
+    pub(crate) overpass_url: String,
+    pub(crate) overpass_timeout: u64,
+    pub(crate) threads: usize,
+    pub(crate) seed: Option<u64>,
+    pub(crate) household_distribution: String,
+    pub(crate) household_presets: BTreeMap<String, HouseholdDistribution>,
+    pub(crate) household_class_distributions: BTreeMap<BuildingClass, String>,
+    pub(crate) distribution_mode: DistributionMode,
+    pub(crate) lenient: bool,
+    pub(crate) output_tags: Vec<String>,
+    pub(crate) flat_estimator: FlatEstimator,
+    pub(crate) area_per_flat: BTreeMap<BuildingClass, f64>,
+    pub(crate) floor_area_per_flat: BTreeMap<BuildingClass, f64>,
+    pub(crate) level_height: f64,
+    pub(crate) roof_level_share: f64,
+    pub(crate) mixed_use_tags: Vec<String>,
+    pub(crate) mixed_use_levels: f64,
+    pub(crate) mixed_use_share: f64,
+    pub(crate) interpolation_distance: f64,
+    pub(crate) housenumber_range_mode: RangeMode,
+    pub(crate) housenumber_locales: Vec<Locale>,
 }
 
 impl Config {
     pub fn builder() -> ConfigBuilder {
         ConfigBuilder::new()
     }
+
+    /// Creates the random number generator for the population distribution.
+    ///
+    /// With a configured seed, the same input and crate version always produce identical output.
+    pub fn rng(&self) -> ChaCha8Rng {
+        match self.seed {
+            Some(seed) => ChaCha8Rng::seed_from_u64(seed),
+            None => ChaCha8Rng::from_entropy(),
+        }
+    }
+
+    /// Gets the first classification rule matching the tags
+    pub(crate) fn classification_rule(&self, tags: &Tags) -> Option<&ClassificationRule> {
+        self.classification_rules
+            .iter()
+            .find(|rule| rule.matches(tags))
+    }
+
+    /// Gets the household distribution of a building class, preferring configured presets over built-in ones
+    pub(crate) fn household_distribution(
+        &self,
+        class: BuildingClass,
+    ) -> Result<HouseholdDistribution, Error> {
+        let name = self
+            .household_class_distributions
+            .get(&class)
+            .unwrap_or(&self.household_distribution);
+        self.household_presets
+            .get(name)
+            .cloned()
+            .or_else(|| HouseholdDistribution::preset(name))
+            .ok_or_else(|| Error::UnknownHouseholdDistribution(name.clone()))
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        ConfigBuilder::new().build()
+    }
 }
 
 #[derive(Default)]
@@ -25,9 +120,28 @@ pub struct ConfigBuilder {
     housenumber_factor: usize,
     exclude_landuse: Vec<String>,
     exclude_tags: Vec<String>,
-    single_home_list: Vec<String>,
-    apartment_list: Vec<String>,
-    unspecified_list: Vec<String>,
+    classification_rules: Vec<ClassificationRule>,
+    overpass_url: String,
+    overpass_timeout: u64,
+    threads: usize,
+    seed: Option<u64>,
+    household_distribution: String,
+    household_presets: BTreeMap<String, HouseholdDistribution>,
+    household_class_distributions: BTreeMap<BuildingClass, String>,
+    distribution_mode: DistributionMode,
+    lenient: bool,
+    output_tags: Vec<String>,
+    flat_estimator: FlatEstimator,
+    area_per_flat: BTreeMap<BuildingClass, f64>,
+    floor_area_per_flat: BTreeMap<BuildingClass, f64>,
+    level_height: f64,
+    roof_level_share: f64,
+    mixed_use_tags: Vec<String>,
+    mixed_use_levels: f64,
+    mixed_use_share: f64,
+    interpolation_distance: f64,
+    housenumber_range_mode: RangeMode,
+    housenumber_locales: Vec<Locale>,
 }
 
 impl ConfigBuilder {
@@ -45,9 +159,45 @@ impl ConfigBuilder {
                 "retail".to_string(),
             ],
             exclude_tags: vec!["amenity".to_string(), "leisure".to_string()],
-            single_home_list: vec!["house".to_string(), "detached".to_string()],
-            apartment_list: vec!["apartments".to_string(), "residential".to_string()],
-            unspecified_list: vec!["terrace".to_string(), "semidetached_house".to_string()],
+            classification_rules: ClassificationRule::defaults(),
+            overpass_url: "https://overpass-api.de/api/interpreter".to_string(),
+            overpass_timeout: 180,
+            threads: 0,
+            seed: None,
+            household_distribution: "germany".to_string(),
+            household_presets: BTreeMap::new(),
+            household_class_distributions: BTreeMap::new(),
+            distribution_mode: DistributionMode::Random,
+            lenient: true,
+            output_tags: vec![
+                "building".to_string(),
+                "building:levels".to_string(),
+                "addr:*".to_string(),
+            ],
+            flat_estimator: FlatEstimator::Tags,
+            area_per_flat: BTreeMap::from([
+                (BuildingClass::Apartment, 80.0),
+                (BuildingClass::Unspecified, 100.0),
+                (BuildingClass::Other, 120.0),
+            ]),
+            floor_area_per_flat: BTreeMap::from([
+                (BuildingClass::Apartment, 90.0),
+                (BuildingClass::Unspecified, 110.0),
+                (BuildingClass::Other, 130.0),
+            ]),
+            level_height: 3.0,
+            roof_level_share: 0.5,
+            mixed_use_tags: vec![
+                "shop".to_string(),
+                "office".to_string(),
+                "craft".to_string(),
+                "amenity".to_string(),
+            ],
+            mixed_use_levels: 1.0,
+            mixed_use_share: 0.5,
+            interpolation_distance: 30.0,
+            housenumber_range_mode: RangeMode::Inferred,
+            housenumber_locales: vec![],
         }
     }
 
@@ -81,18 +231,144 @@ impl ConfigBuilder {
         self
     }
 
-    pub fn single_home_list(mut self, single_home_list: Vec<String>) -> ConfigBuilder {
-        self.single_home_list = single_home_list;
+    /// Rules classifying buildings by their tags, evaluated in order
+    pub fn classification_rules(
+        mut self,
+        classification_rules: Vec<ClassificationRule>,
+    ) -> ConfigBuilder {
+        self.classification_rules = classification_rules;
+        self
+    }
+
+    pub fn overpass_url(mut self, overpass_url: String) -> ConfigBuilder {
+        self.overpass_url = overpass_url;
+        self
+    }
+
+    pub fn overpass_timeout(mut self, overpass_timeout: u64) -> ConfigBuilder {
+        self.overpass_timeout = overpass_timeout;
+        self
+    }
+
+    /// Number of threads used for building calculations, 0 uses all available cores
+    pub fn threads(mut self, threads: usize) -> ConfigBuilder {
+        self.threads = threads;
+        self
+    }
+
+    pub fn seed(mut self, seed: Option<u64>) -> ConfigBuilder {
+        self.seed = seed;
+        self
+    }
+
+    /// Name of the household distribution preset applied to all building classes without override
+    pub fn household_distribution(mut self, household_distribution: String) -> ConfigBuilder {
+        self.household_distribution = household_distribution;
+        self
+    }
+
+    pub fn household_preset(
+        mut self,
+        name: String,
+        distribution: HouseholdDistribution,
+    ) -> ConfigBuilder {
+        self.household_presets.insert(name, distribution);
+        self
+    }
+
+    pub fn household_class_distribution(
+        mut self,
+        class: BuildingClass,
+        household_distribution: String,
+    ) -> ConfigBuilder {
+        self.household_class_distributions
+            .insert(class, household_distribution);
+        self
+    }
+
+    pub fn distribution_mode(mut self, distribution_mode: DistributionMode) -> ConfigBuilder {
+        self.distribution_mode = distribution_mode;
+        self
+    }
+
+    /// Whether malformed tags are reported and replaced by fallbacks instead of failing the calculation
+    pub fn lenient(mut self, lenient: bool) -> ConfigBuilder {
+        self.lenient = lenient;
+        self
+    }
+
+    /// Source tags written to the output, a trailing `*` matches any key with that prefix
+    pub fn output_tags(mut self, output_tags: Vec<String>) -> ConfigBuilder {
+        self.output_tags = output_tags;
+        self
+    }
+
+    pub fn flat_estimator(mut self, flat_estimator: FlatEstimator) -> ConfigBuilder {
+        self.flat_estimator = flat_estimator;
+        self
+    }
+
+    /// Footprint area in m² per flat and storey of a building class, used by the area estimator
+    pub fn area_per_flat(mut self, class: BuildingClass, area_per_flat: f64) -> ConfigBuilder {
+        self.area_per_flat.insert(class, area_per_flat);
+        self
+    }
+
+    /// Gross floor area in m² per flat of a building class, used by the floor area estimator
+    pub fn floor_area_per_flat(
+        mut self,
+        class: BuildingClass,
+        floor_area_per_flat: f64,
+    ) -> ConfigBuilder {
+        self.floor_area_per_flat.insert(class, floor_area_per_flat);
+        self
+    }
+
+    /// Height of a storey in meters, used to derive storeys from `height` without `building:levels`
+    pub fn level_height(mut self, level_height: f64) -> ConfigBuilder {
+        self.level_height = level_height;
+        self
+    }
+
+    /// Share of a full storey counted as habitable for each `roof:levels`
+    pub fn roof_level_share(mut self, roof_level_share: f64) -> ConfigBuilder {
+        self.roof_level_share = roof_level_share;
+        self
+    }
+
+    /// Keys of non-residential pois turning the building they are placed in or on into a mixed use building
+    pub fn mixed_use_tags(mut self, mixed_use_tags: Vec<String>) -> ConfigBuilder {
+        self.mixed_use_tags = mixed_use_tags;
+        self
+    }
+
+    /// Storeys of a mixed use building with known levels considered non-residential
+    pub fn mixed_use_levels(mut self, mixed_use_levels: f64) -> ConfigBuilder {
+        self.mixed_use_levels = mixed_use_levels;
+        self
+    }
+
+    /// Share of flats removed from a mixed use building without known levels
+    pub fn mixed_use_share(mut self, mixed_use_share: f64) -> ConfigBuilder {
+        self.mixed_use_share = mixed_use_share;
+        self
+    }
+
+    /// Maximum distance in meters of interpolated house numbers to the building they are assigned to
+    pub fn interpolation_distance(mut self, interpolation_distance: f64) -> ConfigBuilder {
+        self.interpolation_distance = interpolation_distance;
         self
     }
 
-    pub fn apartment_list(mut self, apartment_list: Vec<String>) -> ConfigBuilder {
-        self.apartment_list = apartment_list;
+    /// How house number ranges like `2-8` are expanded
+    pub fn housenumber_range_mode(mut self, housenumber_range_mode: RangeMode) -> ConfigBuilder {
+        self.housenumber_range_mode = housenumber_range_mode;
         self
     }
 
-    pub fn unspecified_list(mut self, unspecified_list: Vec<String>) -> ConfigBuilder {
-        self.unspecified_list = unspecified_list;
+    /// Locale specific house number forms like `Nr. 5` or `10 et 12` to accept
+    pub fn housenumber_locales(mut self, housenumber_locales: Vec<Locale>) -> ConfigBuilder {
+        self.housenumber_locales = housenumber_locales;
         self
     }
 
@@ -104,9 +380,28 @@ impl ConfigBuilder {
             housenumber_factor: self.housenumber_factor,
             exclude_landuse: self.exclude_landuse,
             exclude_tags: self.exclude_tags,
-            single_home_list: self.single_home_list,
-            apartment_list: self.apartment_list,
-            unspecified_list: self.unspecified_list,
+            classification_rules: self.classification_rules,
+            overpass_url: self.overpass_url,
+            overpass_timeout: self.overpass_timeout,
+            threads: self.threads,
+            seed: self.seed,
+            household_distribution: self.household_distribution,
+            household_presets: self.household_presets,
+            household_class_distributions: self.household_class_distributions,
+            distribution_mode: self.distribution_mode,
+            lenient: self.lenient,
+            output_tags: self.output_tags,
+            flat_estimator: self.flat_estimator,
+            area_per_flat: self.area_per_flat,
+            floor_area_per_flat: self.floor_area_per_flat,
+            level_height: self.level_height,
+            roof_level_share: self.roof_level_share,
+            mixed_use_tags: self.mixed_use_tags,
+            mixed_use_levels: self.mixed_use_levels,
+            mixed_use_share: self.mixed_use_share,
+            interpolation_distance: self.interpolation_distance,
+            housenumber_range_mode: self.housenumber_range_mode,
+            housenumber_locales: self.housenumber_locales,
         }
     }
 }
@@ -0,0 +1,86 @@
+use geo::Coord;
+use geo::Point;
+use osmpbfreader::Node;
+use osmpbfreader::NodeId;
+use osmpbfreader::OsmId;
+use osmpbfreader::OsmObj;
+use std::collections::BTreeMap;
+
+use crate::Error;
+
+/// Osm stores coordinates as fixed point numbers in units of 100 nanodegrees
+const DECIMICRO: f64 = 10000000.;
+
+/// Position of a node with the longitude as `x` and the latitude as `y`, the axis order of all geo types
+pub(crate) fn node_coord(node: &Node) -> Coord {
+    Coord {
+        x: node.decimicro_lon as f64 / DECIMICRO,
+        y: node.decimicro_lat as f64 / DECIMICRO,
+    }
+}
+
+pub(crate) fn node_point(node: &Node) -> Point {
+    Point::from(node_coord(node))
+}
+
+/// Position of a node referenced by a way or relation, failing if the node is missing in the data
+pub(crate) fn referenced_coord(
+    object: OsmId,
+    node_id: NodeId,
+    osm_objs: &BTreeMap<OsmId, OsmObj>,
+) -> Result<Coord, Error> {
+    match osm_objs.get(&OsmId::Node(node_id)) {
+        Some(OsmObj::Node(node)) => Ok(node_coord(node)),
+        _ => Err(Error::MissingNode {
+            object,
+            node: node_id,
+        }),
+    }
+}
+
+/// Converts degrees into the fixed point representation of nodes
+pub(crate) fn decimicro(degrees: f64) -> i32 {
+    (degrees * DECIMICRO).round() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(lat: f64, lon: f64) -> Node {
+        Node {
+            id: NodeId(1),
+            tags: Default::default(),
+            decimicro_lat: decimicro(lat),
+            decimicro_lon: decimicro(lon),
+        }
+    }
+
+    #[test]
+    fn converts_longitude_to_x() {
+        let point = node_point(&node(52.5163, 13.3777));
+        assert!((point.x() - 13.3777).abs() < 1e-9);
+        assert!((point.y() - 52.5163).abs() < 1e-9);
+
+        let point = node_point(&node(-33.8568, 151.2153));
+        assert!((point.x() - 151.2153).abs() < 1e-9);
+        assert!((point.y() + 33.8568).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reports_missing_referenced_nodes() {
+        let mut osm_objs = BTreeMap::new();
+        osm_objs.insert(OsmId::Node(NodeId(1)), OsmObj::Node(node(47.9, 13.8)));
+        let way = OsmId::Way(osmpbfreader::WayId(2));
+
+        let coord = referenced_coord(way, NodeId(1), &osm_objs).unwrap();
+        assert_eq!(coord, node_coord(&node(47.9, 13.8)));
+        assert!(matches!(
+            referenced_coord(way, NodeId(3), &osm_objs),
+            Err(Error::MissingNode {
+                node: NodeId(3),
+                ..
+            })
+        ));
+    }
+}
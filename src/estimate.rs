@@ -0,0 +1,135 @@
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Rule deciding about the base flat count of a building
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FlatRule {
+    /// Flat count given by `building:flats`
+    ExplicitFlats,
+    /// Flats listed by `addr:flats` on the building or on nodes inside or on it, e.g. entrances
+    AddrFlats,
+    /// Distinct `addr:unit` nodes inside or on the building
+    AddrUnits,
+    /// Fixed flat count of the matched classification rule
+    Fixed,
+    /// House numbers multiplied by the factor of the matched classification rule
+    HouseNumberFactor,
+    /// Default flat count of the matched classification rule without house numbers
+    DefaultFlats,
+    /// Footprint area divided by the area per flat of the building class
+    FootprintArea,
+    /// Footprint area times habitable storeys divided by the floor area per flat of the building class
+    FloorArea,
+    /// No rule matched
+    None,
+}
+
+impl Display for FlatRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlatRule::ExplicitFlats => write!(f, "explicit building:flats"),
+            FlatRule::AddrFlats => write!(f, "addr:flats"),
+            FlatRule::AddrUnits => write!(f, "addr:unit nodes"),
+            FlatRule::Fixed => write!(f, "fixed flat count"),
+            FlatRule::HouseNumberFactor => write!(f, "house numbers with factor"),
+            FlatRule::DefaultFlats => write!(f, "default flats without house numbers"),
+            FlatRule::FootprintArea => write!(f, "footprint area"),
+            FlatRule::FloorArea => write!(f, "gross floor area"),
+            FlatRule::None => write!(f, "no rule"),
+        }
+    }
+}
+
+/// Explains how the flat count of a building was estimated
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FlatEstimate {
+    pub rule: FlatRule,
+    /// Name of the matched classification rule
+    pub classification: Option<String>,
+    pub house_numbers: usize,
+    /// Number of building parts aggregated into the building
+    pub parts: usize,
+    /// Geodesic footprint area in m², if applied
+    pub area: Option<f64>,
+    /// Habitable storeys, if the floor area was applied
+    pub storeys: Option<f64>,
+    /// Footprint or floor area per flat in m² of the building class, if the area was applied
+    pub area_per_flat: Option<f64>,
+    /// House number factor, if applied
+    pub housenumber_factor: Option<usize>,
+    /// Building levels multiplied in, if tagged
+    pub levels: Option<usize>,
+    /// Level factor, if levels were applied
+    pub level_factor: Option<usize>,
+    /// Reason the building is considered mixed use, e.g. `shop=bakery on node/123`
+    pub mixed_use: Option<String>,
+    /// Non-residential storeys removed from a mixed use building with known levels
+    pub mixed_use_levels: Option<f64>,
+    /// Share of flats removed from a mixed use building without known levels
+    pub mixed_use_share: Option<f64>,
+    pub flats: usize,
+}
+
+impl FlatEstimate {
+    pub(crate) fn new(rule: FlatRule, house_numbers: usize, flats: usize) -> FlatEstimate {
+        FlatEstimate {
+            rule,
+            classification: None,
+            house_numbers,
+            parts: 0,
+            area: None,
+            storeys: None,
+            area_per_flat: None,
+            housenumber_factor: None,
+            levels: None,
+            level_factor: None,
+            mixed_use: None,
+            mixed_use_levels: None,
+            mixed_use_share: None,
+            flats,
+        }
+    }
+}
+
+impl Display for FlatEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "flats: {}", self.flats)?;
+        writeln!(f, "rule: {}", self.rule)?;
+        if let Some(classification) = &self.classification {
+            writeln!(f, "classification: {classification}")?;
+        }
+        write!(f, "house numbers: {}", self.house_numbers)?;
+        if self.parts > 0 {
+            write!(f, "\nbuilding parts: {}", self.parts)?;
+        }
+        match (self.area, self.storeys, self.area_per_flat) {
+            (Some(area), Some(storeys), Some(area_per_flat)) => write!(
+                f,
+                "\nfloor area: {:.1} m² ({area:.1} m² footprint × {storeys:.1} storeys, {area_per_flat} m² per flat)",
+                area * storeys
+            )?,
+            (Some(area), None, Some(area_per_flat)) => {
+                write!(f, "\narea: {area:.1} m² ({area_per_flat} m² per flat)")?
+            }
+            _ => {}
+        }
+        if let Some(housenumber_factor) = self.housenumber_factor {
+            write!(f, "\nhouse number factor: {housenumber_factor}")?;
+        }
+        if let (Some(levels), Some(level_factor)) = (self.levels, self.level_factor) {
+            write!(f, "\nlevels: {levels} (level factor {level_factor})")?;
+        }
+        if let Some(mixed_use) = &self.mixed_use {
+            write!(f, "\nmixed use: {mixed_use}")?;
+            if let Some(mixed_use_levels) = self.mixed_use_levels {
+                write!(f, " ({mixed_use_levels} non-residential levels)")?;
+            }
+            if let Some(mixed_use_share) = self.mixed_use_share {
+                write!(f, " ({mixed_use_share} non-residential share)")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,27 +1,45 @@
+use geojson::feature::Id;
 use geojson::Feature;
 use geojson::FeatureCollection;
 use geojson::GeoJson;
 
+use crate::pbf::osm_type;
 use crate::pbf::Building;
 use crate::pbf::GenericGeometry;
 
-pub fn write_polygons_to_geojson(buildings: &Vec<Building>) -> GeoJson {
+/// Writes buildings as geojson features, optionally with the explanation of their flat estimate
+pub fn write_polygons_to_geojson(buildings: &Vec<Building>, explain: bool) -> GeoJson {
     let mut features = vec![];
 
     for building in buildings {
+        let osm_type = osm_type(building.id);
         let mut tags_map = serde_json::Map::new();
+        tags_map.insert("osm_type".to_string(), osm_type.into());
+        tags_map.insert("osm_id".to_string(), building.id.inner_id().into());
+        for (key, value) in &building.tags {
+            tags_map.insert(key.clone(), value.clone().into());
+        }
         tags_map.insert("flats".to_string(), building.flats.into());
         tags_map.insert("pop".to_string(), building.pop.into());
+        if explain {
+            tags_map.insert(
+                "estimate".to_string(),
+                serde_json::to_value(&building.estimate).unwrap_or_default(),
+            );
+        }
 
         let geometry = match &building.geometry {
             GenericGeometry::GenericPolygon(polygon) => geojson::Geometry::from(polygon),
+            GenericGeometry::GenericMultiPolygon(multi_polygon) => {
+                geojson::Geometry::from(multi_polygon)
+            }
             GenericGeometry::GenericPoint(point) => geojson::Geometry::from(point),
         };
 
         let feature = Feature {
             bbox: None,
             geometry: Some(geometry),
-            id: None,
+            id: Some(Id::String(format!("{osm_type}/{}", building.id.inner_id()))),
             properties: Some(tags_map),
             foreign_members: None,
         };
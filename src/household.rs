@@ -0,0 +1,117 @@
+use rand::prelude::Distribution;
+use rand::Rng;
+use statrs::distribution::Categorical;
+
+use crate::Error;
+
+/// Household sizes of germany (1, 2, 3, 4, 5+ persons)
+const GERMANY: [f64; 5] = [0.20737853, 0.33310260, 0.17661846, 0.18911436, 0.09378605];
+
+/// Share of households by person count, starting at one person. The last bucket is open-ended.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+pub struct HouseholdDistribution {
+    pub(crate) shares: Vec<f64>,
+    /// Mean size of households in the open-ended top bucket, at least its lower bound
+    pub(crate) top_bucket_mean: f64,
+}
+
+impl HouseholdDistribution {
+    pub fn new(shares: Vec<f64>, top_bucket_mean: f64) -> HouseholdDistribution {
+        HouseholdDistribution {
+            shares,
+            top_bucket_mean,
+        }
+    }
+
+    /// Gets a built-in distribution by name
+    pub(crate) fn preset(name: &str) -> Option<HouseholdDistribution> {
+        match name {
+            "germany" => Some(HouseholdDistribution::new(GERMANY.to_vec(), 5.0)),
+            _ => None,
+        }
+    }
+}
+
+/// Samples household sizes from a distribution
+pub(crate) struct HouseholdSampler {
+    categorical: Categorical,
+    top_bucket: u64,
+    /// Probability of each additional person in the top bucket (geometric distribution)
+    extra_person_probability: f64,
+}
+
+impl HouseholdSampler {
+    pub(crate) fn new(distribution: &HouseholdDistribution) -> Result<HouseholdSampler, Error> {
+        let categorical =
+            Categorical::new(&distribution.shares).map_err(Error::InvalidHouseholdDistribution)?;
+        let top_bucket = distribution.shares.len() as u64;
+        let extra_persons = (distribution.top_bucket_mean - top_bucket as f64).max(0.0);
+        Ok(HouseholdSampler {
+            categorical,
+            top_bucket,
+            extra_person_probability: extra_persons / (1.0 + extra_persons),
+        })
+    }
+
+    /// Samples the number of persons in a household
+    pub(crate) fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> u64 {
+        let mut persons = self.categorical.sample(rng) as u64 + 1;
+        if persons == self.top_bucket && self.extra_person_probability > 0.0 {
+            while rng.gen_bool(self.extra_person_probability) {
+                persons += 1;
+            }
+        }
+        persons
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn samples(distribution: HouseholdDistribution, count: usize) -> Vec<u64> {
+        let sampler = HouseholdSampler::new(&distribution).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        (0..count).map(|_| sampler.sample(&mut rng)).collect()
+    }
+
+    #[test]
+    fn provides_presets_by_name() {
+        let germany = HouseholdDistribution::preset("germany").unwrap();
+        assert_eq!(germany.shares, GERMANY);
+        assert_eq!(germany.top_bucket_mean, 5.0);
+        assert_eq!(HouseholdDistribution::preset("atlantis"), None);
+
+        let sizes = samples(germany, 1000);
+        assert!(sizes.iter().all(|persons| *persons >= 1));
+        assert!(sizes.contains(&1) && sizes.contains(&5));
+    }
+
+    #[test]
+    fn extends_the_open_top_bucket() {
+        let sizes = samples(HouseholdDistribution::new(vec![0.0, 1.0], 4.0), 10000);
+        assert!(sizes.iter().all(|persons| *persons >= 2));
+        assert!(sizes.iter().any(|persons| *persons > 2));
+        let mean = sizes.iter().sum::<u64>() as f64 / sizes.len() as f64;
+        assert!((mean - 4.0).abs() < 0.1, "mean of {mean} persons");
+
+        // A top bucket mean below its lower bound keeps the bucket closed
+        let sizes = samples(HouseholdDistribution::new(vec![0.0, 0.0, 1.0], 2.0), 100);
+        assert!(sizes.iter().all(|persons| *persons == 3));
+    }
+
+    #[test]
+    fn rejects_invalid_distributions() {
+        for shares in [vec![], vec![0.0, 0.0], vec![0.5, -0.1], vec![f64::NAN]] {
+            assert!(
+                matches!(
+                    HouseholdSampler::new(&HouseholdDistribution::new(shares.clone(), 5.0)),
+                    Err(Error::InvalidHouseholdDistribution(_))
+                ),
+                "{shares:?}"
+            );
+        }
+    }
+}
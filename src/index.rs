@@ -0,0 +1,132 @@
+use geo::BoundingRect;
+use geo::Point;
+use osmpbfreader::OsmId;
+use rstar::Envelope;
+use rstar::RTree;
+use rstar::RTreeObject;
+use rstar::AABB;
+use std::collections::BTreeMap;
+
+use crate::pbf::GenericGeometry;
+use crate::pbf::GenericWay;
+use crate::pbf::HouseNumberPoint;
+
+impl GenericGeometry {
+    /// Gets the axis aligned bounding box of the geometry
+    pub(crate) fn envelope(&self) -> AABB<Point> {
+        let rect = match self {
+            GenericGeometry::GenericPolygon(polygon) => polygon.bounding_rect(),
+            GenericGeometry::GenericMultiPolygon(multi_polygon) => multi_polygon.bounding_rect(),
+            GenericGeometry::GenericPoint(point) => Some(point.bounding_rect()),
+        };
+        match rect {
+            Some(rect) => AABB::from_corners(rect.min().into(), rect.max().into()),
+            // Empty geometries get an inverted envelope which never intersects anything
+            None => AABB::new_empty(),
+        }
+    }
+}
+
+impl RTreeObject for HouseNumberPoint {
+    type Envelope = AABB<Point>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point(self.point)
+    }
+}
+
+impl RTreeObject for GenericWay {
+    type Envelope = AABB<Point>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.geometry.envelope()
+    }
+}
+
+/// R-trees over house number points, exclude areas, building parts, mixed use pois and flat address nodes,
+/// built once per calculation
+pub(crate) struct SpatialIndex {
+    house_numbers: RTree<HouseNumberPoint>,
+    exclude_areas: RTree<GenericWay>,
+    building_parts: RTree<GenericWay>,
+    part_relations: BTreeMap<OsmId, Vec<OsmId>>,
+    mixed_use_pois: RTree<GenericWay>,
+    flat_addresses: RTree<GenericWay>,
+}
+
+impl SpatialIndex {
+    pub(crate) fn new(
+        house_numbers: Vec<HouseNumberPoint>,
+        exclude_areas: Vec<GenericWay>,
+        building_parts: Vec<GenericWay>,
+        part_relations: BTreeMap<OsmId, Vec<OsmId>>,
+        mixed_use_pois: Vec<GenericWay>,
+        flat_addresses: Vec<GenericWay>,
+    ) -> Self {
+        SpatialIndex {
+            house_numbers: RTree::bulk_load(house_numbers),
+            exclude_areas: RTree::bulk_load(exclude_areas),
+            building_parts: RTree::bulk_load(building_parts),
+            part_relations,
+            mixed_use_pois: RTree::bulk_load(mixed_use_pois),
+            flat_addresses: RTree::bulk_load(flat_addresses),
+        }
+    }
+
+    /// Gets all house number points inside the geometry
+    pub(crate) fn house_numbers_in<'a>(
+        &'a self,
+        geometry: &'a GenericGeometry,
+    ) -> impl Iterator<Item = &'a HouseNumberPoint> {
+        self.house_numbers
+            .locate_in_envelope(&geometry.envelope())
+            .filter(|house_number| geometry.contains_point(&house_number.point))
+    }
+
+    /// Gets all non-residential pois inside the geometry
+    pub(crate) fn mixed_use_pois_in<'a>(
+        &'a self,
+        geometry: &'a GenericGeometry,
+    ) -> impl Iterator<Item = &'a GenericWay> {
+        self.mixed_use_pois
+            .locate_in_envelope(&geometry.envelope())
+            .filter(|poi| poi.excludes(geometry))
+    }
+
+    /// Gets all nodes listing flats inside or on the outline of the geometry
+    pub(crate) fn flat_addresses_on<'a>(
+        &'a self,
+        geometry: &'a GenericGeometry,
+    ) -> impl Iterator<Item = &'a GenericWay> {
+        self.flat_addresses
+            .locate_in_envelope(&geometry.envelope())
+            .filter(|node| match node.geometry {
+                GenericGeometry::GenericPoint(point) => geometry.intersects_point(&point),
+                _ => false,
+            })
+    }
+
+    /// Checks if the geometry is excluded by any exclude area
+    pub(crate) fn is_excluded(&self, geometry: &GenericGeometry) -> bool {
+        self.exclude_areas
+            .locate_in_envelope_intersecting(&geometry.envelope())
+            .any(|area| area.excludes(geometry))
+    }
+
+    /// Gets the parts of a building, by building relation if there is one, otherwise all parts centered inside its outline
+    pub(crate) fn parts_of<'a>(
+        &'a self,
+        building: &'a GenericWay,
+    ) -> impl Iterator<Item = &'a GenericWay> {
+        let relation_parts = self.part_relations.get(&building.id);
+        self.building_parts
+            .locate_in_envelope_intersecting(&building.geometry.envelope())
+            .filter(move |part| match relation_parts {
+                Some(relation_parts) => relation_parts.contains(&part.id),
+                None => part
+                    .geometry
+                    .centroid()
+                    .is_some_and(|centroid| building.geometry.contains_point(&centroid)),
+            })
+    }
+}
@@ -0,0 +1,431 @@
+use geo::ClosestPoint;
+use geo::HaversineDistance;
+use geo::InteriorPoint;
+use geo::LineInterpolatePoint;
+use geo::LineString;
+use geo::Point;
+use osmpbfreader::OsmId;
+use osmpbfreader::OsmObj;
+use osmpbfreader::Way;
+use rstar::primitives::GeomWithData;
+use rstar::primitives::Rectangle;
+use rstar::RTree;
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::coordinates::node_coord;
+use crate::parser::housenumber::letter_index;
+use crate::parser::housenumber::HouseNumberList;
+use crate::parser::housenumber::SingleHouseNumber;
+use crate::pbf::GenericGeometry;
+use crate::pbf::GenericWay;
+use crate::pbf::HouseNumberPoint;
+use crate::report::DataQualityReport;
+use crate::Error;
+
+/// Interpolations spanning more house numbers are considered broken
+const MAX_INTERPOLATED: u32 = 1000;
+
+/// Check if osm obj is an address interpolation way
+pub(crate) fn is_interpolation_way(obj: &OsmObj) -> bool {
+    obj.is_way() && obj.tags().contains_key("addr:interpolation")
+}
+
+/// Step between interpolated house numbers
+enum Step {
+    Numeric(u32),
+    /// Every second number, all of them odd (remainder 1) or even (remainder 0)
+    Parity(u32),
+    Alphabetic,
+}
+
+/// Expands all address interpolation ways into virtual house number points between their address nodes
+pub(crate) fn load_interpolations(
+    osm_objs: &BTreeMap<OsmId, OsmObj>,
+    config: &Config,
+    report: &mut DataQualityReport,
+) -> Result<Vec<HouseNumberPoint>, Error> {
+    let mut points = vec![];
+    for way in osm_objs
+        .values()
+        .filter(|obj| is_interpolation_way(obj))
+        .filter_map(|obj| obj.way())
+    {
+        let interpolation = &way.tags["addr:interpolation"];
+        let step = match interpolation.as_str() {
+            "all" => Step::Numeric(1),
+            "odd" => Step::Parity(1),
+            "even" => Step::Parity(0),
+            "alphabetic" => Step::Alphabetic,
+            step => match step.parse::<u32>() {
+                Ok(step) if step > 0 => Step::Numeric(step),
+                _ => {
+                    let id = OsmId::Way(way.id);
+                    report.record(id, "addr:interpolation", interpolation, "ignored", config)?;
+                    continue;
+                }
+            },
+        };
+        interpolate_way(way, &step, osm_objs, config, report, &mut points)?;
+    }
+    Ok(points)
+}
+
+/// Interpolates house numbers between each pair of consecutive address nodes of the way
+fn interpolate_way(
+    way: &Way,
+    step: &Step,
+    osm_objs: &BTreeMap<OsmId, OsmObj>,
+    config: &Config,
+    report: &mut DataQualityReport,
+    points: &mut Vec<HouseNumberPoint>,
+) -> Result<(), Error> {
+    let mut coords = Vec::with_capacity(way.nodes.len());
+    let mut anchors = vec![];
+    for node_id in &way.nodes {
+        // Interpolations cut off at the border of an extract are skipped
+        let Some(OsmObj::Node(node)) = osm_objs.get(&OsmId::Node(*node_id)) else {
+            return Ok(());
+        };
+        if let Some(housenumber) = node.tags.get("addr:housenumber") {
+            anchors.push((coords.len(), housenumber));
+        }
+        coords.push(node_coord(node));
+    }
+
+    for pair in anchors.windows(2) {
+        let [(start_index, start), (end_index, end)] = pair else {
+            continue;
+        };
+        let Some(numbers) = interpolated_numbers(start, end, step) else {
+            let value = format!("{} {start}-{end}", way.tags["addr:interpolation"]);
+            let id = OsmId::Way(way.id);
+            report.record(id, "addr:interpolation", &value, "ignored", config)?;
+            continue;
+        };
+        let line = LineString::from(coords[*start_index..=*end_index].to_vec());
+        let count = numbers.len() + 1;
+        for (position, number) in numbers.into_iter().enumerate() {
+            let fraction = (position + 1) as f64 / count as f64;
+            let Some(point) = line.line_interpolate_point(fraction) else {
+                continue;
+            };
+            let numbers = HouseNumberList::from_iter([number]);
+            points.push(HouseNumberPoint { point, numbers });
+        }
+    }
+    Ok(())
+}
+
+/// Gets the house numbers strictly between two address nodes in the direction of the way, `None` if they cannot
+/// be interpolated
+fn interpolated_numbers(start: &str, end: &str, step: &Step) -> Option<Vec<SingleHouseNumber>> {
+    let start = start.parse::<SingleHouseNumber>().ok()?;
+    let end = end.parse::<SingleHouseNumber>().ok()?;
+    match step {
+        Step::Numeric(step) => numeric_range(&start, &end, *step),
+        Step::Parity(remainder) => {
+            if start.number() % 2 != *remainder || end.number() % 2 != *remainder {
+                return None;
+            }
+            numeric_range(&start, &end, 2)
+        }
+        Step::Alphabetic => {
+            if start.number() != end.number() {
+                return None;
+            }
+            let start_letter = letter_index(start.suffix())?;
+            let end_letter = letter_index(end.suffix())?;
+            let letters: Vec<u8> = if start_letter <= end_letter {
+                (start_letter + 1..end_letter).collect()
+            } else {
+                (end_letter + 1..start_letter).rev().collect()
+            };
+            Some(
+                letters
+                    .into_iter()
+                    .map(|letter| {
+                        SingleHouseNumber::new(
+                            start.number(),
+                            &((b'a' + letter - 1) as char).to_string(),
+                        )
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Gets the numbers between two plain house numbers, stepping from the start
+fn numeric_range(
+    start: &SingleHouseNumber,
+    end: &SingleHouseNumber,
+    step: u32,
+) -> Option<Vec<SingleHouseNumber>> {
+    if !start.suffix().is_empty() || !end.suffix().is_empty() {
+        return None;
+    }
+    let (start, end) = (start.number(), end.number());
+    if start.abs_diff(end) > MAX_INTERPOLATED {
+        return None;
+    }
+    let numbers: Vec<u32> = if start <= end {
+        (start..end).step_by(step as usize).skip(1).collect()
+    } else {
+        (end + 1..=start)
+            .rev()
+            .step_by(step as usize)
+            .skip(1)
+            .collect()
+    };
+    Some(
+        numbers
+            .into_iter()
+            .map(|number| SingleHouseNumber::new(number, ""))
+            .collect(),
+    )
+}
+
+/// Moves interpolated house numbers outside of any building into the nearest building within the configured
+/// distance. Points without a building nearby are dropped.
+pub(crate) fn assign_to_buildings(
+    points: Vec<HouseNumberPoint>,
+    buildings: &[GenericWay],
+    config: &Config,
+) -> Vec<HouseNumberPoint> {
+    let envelopes: RTree<GeomWithData<Rectangle<Point>, usize>> = RTree::bulk_load(
+        buildings
+            .iter()
+            .enumerate()
+            .filter(|(_, building)| !matches!(building.geometry, GenericGeometry::GenericPoint(_)))
+            .map(|(index, building)| {
+                let envelope = building.geometry.envelope();
+                GeomWithData::new(
+                    Rectangle::from_corners(envelope.lower(), envelope.upper()),
+                    index,
+                )
+            })
+            .collect(),
+    );
+
+    points
+        .into_iter()
+        .filter_map(|mut house_number| {
+            if envelopes
+                .locate_all_at_point(&house_number.point)
+                .any(|envelope| {
+                    buildings[envelope.data]
+                        .geometry
+                        .contains_point(&house_number.point)
+                })
+            {
+                return Some(house_number);
+            }
+
+            // Candidates are ordered by envelope distance, so the first building within range wins
+            let building = envelopes
+                .nearest_neighbor_iter(&house_number.point)
+                .map(|envelope| &buildings[envelope.data].geometry)
+                .take(8)
+                .find(|geometry| {
+                    distance(geometry, &house_number.point)
+                        .is_some_and(|distance| distance <= config.interpolation_distance)
+                })?;
+            house_number.point = interior_point(building)?;
+            Some(house_number)
+        })
+        .collect()
+}
+
+/// Gets the distance in meters from a point to the closest point of a geometry
+fn distance(geometry: &GenericGeometry, point: &Point) -> Option<f64> {
+    let closest = match geometry {
+        GenericGeometry::GenericPolygon(polygon) => polygon.closest_point(point),
+        GenericGeometry::GenericMultiPolygon(multi_polygon) => multi_polygon.closest_point(point),
+        GenericGeometry::GenericPoint(point2) => point2.closest_point(point),
+    };
+    match closest {
+        geo::Closest::Intersection(closest) | geo::Closest::SinglePoint(closest) => {
+            Some(closest.haversine_distance(point))
+        }
+        geo::Closest::Indeterminate => None,
+    }
+}
+
+fn interior_point(geometry: &GenericGeometry) -> Option<Point> {
+    match geometry {
+        GenericGeometry::GenericPolygon(polygon) => polygon.interior_point(),
+        GenericGeometry::GenericMultiPolygon(multi_polygon) => multi_polygon.interior_point(),
+        GenericGeometry::GenericPoint(point) => Some(*point),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coordinates::decimicro;
+    use geo::Polygon;
+    use osmpbfreader::Node;
+    use osmpbfreader::NodeId;
+    use osmpbfreader::Tags;
+    use osmpbfreader::WayId;
+
+    fn numbers(start: &str, end: &str, step: &Step) -> Option<Vec<String>> {
+        interpolated_numbers(start, end, step)
+            .map(|numbers| numbers.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn steps_through_numbers() {
+        let cases: [(&str, &str, Step, Option<&[&str]>); 11] = [
+            ("1", "5", Step::Numeric(1), Some(&["2", "3", "4"])),
+            ("1", "10", Step::Numeric(3), Some(&["4", "7"])),
+            ("1", "9", Step::Parity(1), Some(&["3", "5", "7"])),
+            ("2", "10", Step::Parity(0), Some(&["4", "6", "8"])),
+            ("10", "2", Step::Parity(0), Some(&["8", "6", "4"])),
+            ("10", "1", Step::Numeric(3), Some(&["7", "4"])),
+            ("4", "6", Step::Parity(0), Some(&[])),
+            // Endpoints not matching the parity
+            ("2", "10", Step::Parity(1), None),
+            ("1", "10", Step::Parity(1), None),
+            ("1", "9", Step::Parity(0), None),
+            // Suffixes are only interpolated alphabetically
+            ("1a", "9", Step::Numeric(1), None),
+        ];
+        for (start, end, step, expected) in cases {
+            assert_eq!(
+                numbers(start, end, &step),
+                expected.map(|numbers| numbers.iter().map(ToString::to_string).collect()),
+                "{start}-{end}"
+            );
+        }
+    }
+
+    #[test]
+    fn steps_through_letters() {
+        let letters = |start, end| numbers(start, end, &Step::Alphabetic);
+        assert_eq!(
+            letters("12a", "12d"),
+            Some(vec!["12b".into(), "12c".into()])
+        );
+        assert_eq!(letters("12", "12C"), Some(vec!["12a".into(), "12b".into()]));
+        assert_eq!(
+            letters("12d", "12a"),
+            Some(vec!["12c".into(), "12b".into()])
+        );
+        assert_eq!(letters("12a", "12b"), Some(vec![]));
+        assert_eq!(letters("12a", "14c"), None);
+        assert_eq!(letters("12a", "12bis"), None);
+        assert_eq!(letters("12a", "twelve"), None);
+    }
+
+    #[test]
+    fn rejects_too_wide_interpolations() {
+        let all = |start, end| numbers(start, end, &Step::Numeric(1)).map(|numbers| numbers.len());
+        assert_eq!(all("1", "1001"), Some(999));
+        assert_eq!(all("1001", "1"), Some(999));
+        assert_eq!(all("1", "1002"), None);
+        assert_eq!(all("1", "99999999999"), None);
+    }
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).into(), (*value).into()))
+            .collect()
+    }
+
+    fn interpolation(interpolation: &str, start: &str, end: &str) -> BTreeMap<OsmId, OsmObj> {
+        let mut osm_objs = BTreeMap::new();
+        for (id, lon, housenumber) in [(1, 13.7990, start), (2, 13.8000, end)] {
+            osm_objs.insert(
+                OsmId::Node(NodeId(id)),
+                OsmObj::Node(Node {
+                    id: NodeId(id),
+                    tags: tags(&[("addr:housenumber", housenumber)]),
+                    decimicro_lat: decimicro(47.9180),
+                    decimicro_lon: decimicro(lon),
+                }),
+            );
+        }
+        osm_objs.insert(
+            OsmId::Way(WayId(10)),
+            OsmObj::Way(Way {
+                id: WayId(10),
+                tags: tags(&[("addr:interpolation", interpolation)]),
+                nodes: vec![NodeId(1), NodeId(2)],
+            }),
+        );
+        osm_objs
+    }
+
+    #[test]
+    fn places_numbers_along_the_way_and_reports_mismatches() {
+        let config = Config::builder().build();
+        let mut report = DataQualityReport::default();
+        let points =
+            load_interpolations(&interpolation("even", "2", "8"), &config, &mut report).unwrap();
+        assert!(report.is_empty());
+        let positions: Vec<(String, f64)> = points
+            .iter()
+            .map(|point| (point.numbers.to_string(), point.point.x()))
+            .collect();
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].0, "4");
+        assert!((positions[0].1 - 13.79933).abs() < 1e-5);
+        assert_eq!(positions[1].0, "6");
+        assert!((positions[1].1 - 13.79967).abs() < 1e-5);
+
+        let points =
+            load_interpolations(&interpolation("odd", "2", "10"), &config, &mut report).unwrap();
+        assert!(points.is_empty());
+        let issue = &report.issues()[0];
+        assert_eq!((issue.osm_type.as_str(), issue.osm_id), ("way", 10));
+        assert_eq!(issue.tag, "addr:interpolation");
+        assert_eq!(issue.value, "odd 2-10");
+        assert_eq!(issue.fallback, "ignored");
+    }
+
+    fn house_number(lon: f64, lat: f64) -> HouseNumberPoint {
+        HouseNumberPoint {
+            point: Point::new(lon, lat),
+            numbers: HouseNumberList::from_iter([SingleHouseNumber::new(1, "")]),
+        }
+    }
+
+    #[test]
+    fn snaps_numbers_to_nearby_buildings() {
+        let buildings = [GenericWay {
+            id: OsmId::Way(WayId(1)),
+            geometry: GenericGeometry::GenericPolygon(Polygon::new(
+                LineString::from(vec![
+                    (13.7990, 47.9180),
+                    (13.8000, 47.9180),
+                    (13.8000, 47.9190),
+                    (13.7990, 47.9190),
+                    (13.7990, 47.9180),
+                ]),
+                vec![],
+            )),
+            tags: Tags::new(),
+        }];
+        let config = Config::builder().build();
+        let points = vec![
+            // Inside
+            house_number(13.7995, 47.9185),
+            // About 15 m south of the building
+            house_number(13.7995, 47.91787),
+            // About 75 m east of the building
+            house_number(13.8010, 47.9185),
+        ];
+
+        let points = assign_to_buildings(points, &buildings, &config);
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].point, Point::new(13.7995, 47.9185));
+        assert!(buildings[0].geometry.contains_point(&points[1].point));
+
+        let config = Config::builder().interpolation_distance(100.0).build();
+        let points = assign_to_buildings(vec![house_number(13.8010, 47.9185)], &buildings, &config);
+        assert_eq!(points.len(), 1);
+    }
+}
@@ -3,29 +3,89 @@
 //! This tool automatically distributes a given amount of inhabitants to osm buildings.
 //! The calculation is based on predefined heuristics, calculating a flat count per building and randomly distributing people.
 
+#[doc(hidden)]
+pub mod bench;
+mod classification;
 mod config;
+mod coordinates;
+mod estimate;
 pub mod geometry;
+mod household;
+mod index;
+mod interpolation;
+mod multipolygon;
+mod overpass;
 mod parser;
 mod pbf;
+mod report;
 
-use osmpbfreader::OsmPbfReader;
-use pbf::{is_building, is_exclude_area, is_housenumber_node, load_housenumbers, load_ways};
+use index::SpatialIndex;
+use interpolation::{assign_to_buildings, is_interpolation_way, load_interpolations};
+use osmpbfreader::{NodeId, OsmId, OsmObj, OsmPbfReader};
+use pbf::{
+    is_building, is_building_part, is_building_relation, is_exclude_area, is_flat_address_node,
+    is_housenumber_node, is_mixed_use_poi, load_housenumbers, load_part_relations, load_ways,
+};
 
+use std::collections::BTreeMap;
 use std::fmt::Display;
 
-pub use crate::config::Config;
-pub use crate::pbf::{Building, Buildings, GenericGeometry};
+pub use crate::classification::{ClassificationRule, FlatFormula, TagCondition};
+pub use crate::config::{Config, DistributionMode, FlatEstimator};
+pub use crate::estimate::{FlatEstimate, FlatRule};
+pub use crate::household::HouseholdDistribution;
+pub use crate::overpass::OverpassArea;
+pub use crate::parser::housenumber;
+pub use crate::parser::housenumber::Locale;
+pub use crate::parser::housenumber::RangeMode;
+pub use crate::pbf::{Building, BuildingClass, Buildings, GenericGeometry};
+pub use crate::report::{DataQualityIssue, DataQualityReport};
 
 #[derive(Debug)]
 pub enum Error {
+    PbfError(osmpbfreader::Error),
     OverpassError(reqwest::Error),
+    TemplateError(handlebars::RenderError),
+    JsonError(serde_json::Error),
+    CsvError(csv::Error),
+    ThreadPoolError(rayon::ThreadPoolBuildError),
+    UnknownHouseholdDistribution(String),
+    InvalidHouseholdDistribution(statrs::StatsError),
+    MissingNode {
+        object: OsmId,
+        node: NodeId,
+    },
+    InvalidTag {
+        object: OsmId,
+        key: String,
+        value: String,
+    },
+    NoFlats,
     IOError(std::io::Error),
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::PbfError(err) => write!(f, "failed to read pbf: {err}"),
             Self::OverpassError(err) => write!(f, "failed to query overpass api: {err}"),
+            Self::TemplateError(err) => write!(f, "failed to build overpass query: {err}"),
+            Self::JsonError(err) => write!(f, "failed to write json: {err}"),
+            Self::CsvError(err) => write!(f, "failed to write csv: {err}"),
+            Self::ThreadPoolError(err) => write!(f, "failed to create thread pool: {err}"),
+            Self::UnknownHouseholdDistribution(name) => {
+                write!(f, "unknown household distribution: {name}")
+            }
+            Self::InvalidHouseholdDistribution(err) => {
+                write!(f, "invalid household distribution: {err}")
+            }
+            Self::MissingNode { object, node } => {
+                write!(f, "{object:?} references missing node {}", node.0)
+            }
+            Self::InvalidTag { object, key, value } => {
+                write!(f, "{object:?} has invalid value for {key}: {value}")
+            }
+            Self::NoFlats => write!(f, "no flats to populate"),
             Self::IOError(err) => write!(f, "io error occured: {err}"),
         }
     }
@@ -38,30 +98,125 @@ pub fn calculate_buildings<T: std::io::Read + std::io::Seek>(
     pbf: &mut OsmPbfReader<T>,
     centroid: bool,
     config: &Config,
-) -> Result<Buildings, Error> {
-    // Retrieve objects from pbf
+) -> Result<(Buildings, DataQualityReport), Error> {
+    // Retrieve objects of all categories and their dependencies at once
     log::info!("Loading objects from pbf...");
-    let osm_buildings = pbf.get_objs_and_deps(is_building).unwrap();
-    let osm_housenumbers = pbf.get_objs_and_deps(is_housenumber_node).unwrap();
-    let osm_exclude_areas = pbf
-        .get_objs_and_deps(|obj| is_exclude_area(obj, config))
-        .unwrap();
+    let osm_objs = pbf
+        .get_objs_and_deps(|obj| {
+            is_building(obj)
+                || is_building_part(obj)
+                || is_building_relation(obj)
+                || is_housenumber_node(obj)
+                || is_exclude_area(obj, config)
+                || is_mixed_use_poi(obj, config)
+                || is_flat_address_node(obj)
+                || is_interpolation_way(obj)
+        })
+        .map_err(Error::PbfError)?;
+    log_peak_memory();
 
+    calculate_buildings_from_objs(&osm_objs, centroid, config)
+}
+
+/// Calculates the population of houses in a given area queried from the overpass api
+pub fn calculate_buildings_overpass(
+    area: &OverpassArea,
+    centroid: bool,
+    config: &Config,
+) -> Result<(Buildings, DataQualityReport), Error> {
+    log::info!("Loading objects from overpass api...");
+    let osm_objs = overpass::load_objs(area, config)?;
+
+    calculate_buildings_from_objs(&osm_objs, centroid, config)
+}
+
+/// Calculates the population of houses from already loaded osm objects and their dependencies
+pub fn calculate_buildings_from_objs(
+    osm_objs: &BTreeMap<OsmId, OsmObj>,
+    centroid: bool,
+    config: &Config,
+) -> Result<(Buildings, DataQualityReport), Error> {
+    let mut report = DataQualityReport::default();
     log::info!("Loading ways...");
-    let building_ways = load_ways(osm_buildings);
+    let building_ways = load_ways(osm_objs, is_building, config, &mut report)?;
     log::info!("Loading housenumbers...");
-    let housenumbers = load_housenumbers(osm_housenumbers);
-    log::info!("Creating buildings...");
-    let mut buildings = Buildings::from((building_ways, &housenumbers, config));
+    let mut housenumbers = load_housenumbers(osm_objs, config, &mut report)?;
+    log::info!("Interpolating addresses...");
+    let interpolated = load_interpolations(osm_objs, config, &mut report)?;
+    housenumbers.extend(assign_to_buildings(interpolated, &building_ways, config));
     log::info!("Loading exclude areas...");
-    let areas = load_ways(osm_exclude_areas);
-    if centroid {
-        log::info!("Calculating centroids...");
-        buildings.centroid();
+    let areas = load_ways(
+        osm_objs,
+        |obj| is_exclude_area(obj, config),
+        config,
+        &mut report,
+    )?;
+    log::info!("Loading building parts...");
+    let building_parts = load_ways(osm_objs, is_building_part, config, &mut report)?;
+    let part_relations = load_part_relations(osm_objs);
+    log::info!("Loading mixed use pois...");
+    let mixed_use_pois = load_ways(
+        osm_objs,
+        |obj| is_mixed_use_poi(obj, config),
+        config,
+        &mut report,
+    )?;
+    log::info!("Loading flat addresses...");
+    let flat_addresses = load_ways(osm_objs, is_flat_address_node, config, &mut report)?;
+    log::info!("Building spatial index...");
+    let index = SpatialIndex::new(
+        housenumbers,
+        areas,
+        building_parts,
+        part_relations,
+        mixed_use_pois,
+        flat_addresses,
+    );
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.threads)
+        .build()
+        .map_err(Error::ThreadPoolError)?;
+    let buildings = pool.install(|| {
+        log::info!("Creating buildings...");
+        let (buildings, building_report) = Buildings::from_ways(building_ways, &index, config)?;
+        report.merge(building_report);
+        log::info!("Exclude areas...");
+        let mut buildings = buildings.exclude_in(&index);
+        if centroid {
+            log::info!("Calculating centroids...");
+            buildings.centroid();
+        }
+        Ok::<_, Error>(buildings)
+    })?;
+
+    if !report.is_empty() {
+        log::warn!("Found {} data quality issues", report.issues().len());
     }
-    log::info!("Exclude areas...");
-    buildings = buildings.exclude_in(&areas);
+    log_peak_memory();
     log::info!("Distributing population...");
 
-    Ok(buildings)
+    Ok((buildings, report))
+}
+
+/// Logs the peak resident memory of the process, if the platform reports it
+fn log_peak_memory() {
+    let peak_memory_kb = std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("VmHWM:"))
+                .and_then(|value| {
+                    value
+                        .trim()
+                        .trim_end_matches("kB")
+                        .trim()
+                        .parse::<u64>()
+                        .ok()
+                })
+        });
+    if let Some(peak_memory_kb) = peak_memory_kb {
+        log::info!("Peak memory usage: {} MiB", peak_memory_kb / 1024);
+    }
 }
@@ -2,16 +2,20 @@ use std::fs;
 use std::fs::File;
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use config::Config;
 use openhousepopulator::geometry::write_polygons_to_geojson;
-use openhousepopulator::{calculate_buildings, Error};
+use openhousepopulator::{
+    calculate_buildings, calculate_buildings_overpass, Buildings, DataQualityReport, Error,
+    OverpassArea,
+};
+use osmpbfreader::{NodeId, OsmId, RelationId, WayId};
 use std::io::Write;
 
 /// Simple program to greet a person
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
-struct Args {
+struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -20,9 +24,8 @@ struct Args {
 enum Commands {
     /// populates area by region name
     Populate {
-        /// name of the region
-        #[arg(short, long)]
-        file_string: String,
+        #[command(flatten)]
+        source: Source,
 
         /// inhabitants living in region
         #[arg(short, long)]
@@ -31,15 +34,102 @@ enum Commands {
         /// if result should be returned using centroids
         #[arg(short, long)]
         centroid: bool,
+
+        /// number of threads to use, 0 uses all available cores
+        #[arg(short, long)]
+        threads: Option<u64>,
+
+        /// seed for a reproducible population distribution
+        #[arg(short, long)]
+        seed: Option<u64>,
+
+        /// file to write the data quality report to
+        #[arg(short, long)]
+        report: Option<PathBuf>,
+
+        /// format of the data quality report
+        #[arg(long, value_enum, default_value_t = ReportFormat::Json)]
+        report_format: ReportFormat,
+
+        /// if the explanation of the flat estimate should be added to the output
+        #[arg(short, long)]
+        explain: bool,
     },
+    /// explains the flat estimate of a building
+    Explain {
+        #[command(flatten)]
+        source: Source,
+
+        /// osm id of the building (e.g. way/123)
+        #[arg(long, value_parser = parse_osm_id)]
+        osm_id: OsmId,
+    },
+}
+
+#[derive(Args)]
+struct Source {
+    /// name of the region
+    #[arg(short, long, required_unless_present = "overpass")]
+    file_string: Option<String>,
+
+    /// query region from overpass api by bounding box (south,west,north,east) or area name
+    #[arg(short, long, conflicts_with = "file_string")]
+    overpass: Option<OverpassArea>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReportFormat {
+    Json,
+    Csv,
+}
+
+fn parse_osm_id(osm_id: &str) -> Result<OsmId, String> {
+    let (osm_type, id) = osm_id
+        .split_once('/')
+        .ok_or_else(|| "expected <type>/<id>, e.g. way/123".to_string())?;
+    let id = id
+        .parse()
+        .map_err(|err| format!("invalid id {id}: {err}"))?;
+    match osm_type {
+        "node" => Ok(OsmId::Node(NodeId(id))),
+        "way" => Ok(OsmId::Way(WayId(id))),
+        "relation" => Ok(OsmId::Relation(RelationId(id))),
+        _ => Err(format!("unknown osm type {osm_type}")),
+    }
+}
+
+fn load_buildings(
+    source: &Source,
+    centroid: bool,
+    config: &openhousepopulator::Config,
+) -> (Buildings, DataQualityReport) {
+    match (&source.file_string, &source.overpass) {
+        (Some(file_string), _) => {
+            let file = std::path::Path::new(file_string);
+            let r = std::fs::File::open(file).map_err(Error::IOError).unwrap();
+            let mut pbf = osmpbfreader::OsmPbfReader::new(r);
+            calculate_buildings(&mut pbf, centroid, config).unwrap()
+        }
+        (None, Some(area)) => calculate_buildings_overpass(area, centroid, config).unwrap(),
+        (None, None) => unreachable!("clap requires a file or overpass area"),
+    }
 }
 
 fn main() {
-    let cli = Args::parse();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let cli = Cli::parse();
+
+    let (threads, seed) = match &cli.command {
+        Some(Commands::Populate { threads, seed, .. }) => (*threads, *seed),
+        _ => (None, None),
+    };
 
     let settings = Config::builder()
         // Add in `./Settings.toml`
         .add_source(config::File::with_name("settings"))
+        .set_override_option("threads", threads)
+        .and_then(|builder| builder.set_override_option("seed", seed))
+        .expect("Overriding config by arguments failed.")
         .build()
         .expect("Parsing of config file failed.");
 
@@ -49,26 +139,39 @@ fn main() {
 
     match &cli.command {
         Some(Commands::Populate {
-            file_string,
+            source,
             inhabitants,
             centroid,
+            report,
+            report_format,
+            explain,
+            ..
         }) => {
-            let file = std::path::Path::new(file_string);
-            let r = std::fs::File::open(file).map_err(Error::IOError).unwrap();
-            let mut pbf = osmpbfreader::OsmPbfReader::new(r);
-            let mut buildings =
-                calculate_buildings(&mut pbf, *centroid, &populator_config).unwrap();
-            match inhabitants {
-                Some(inhabitants) => {
-                    buildings.distribute_population(*inhabitants, &populator_config)
+            let (mut buildings, data_quality_report) =
+                load_buildings(source, *centroid, &populator_config);
+            if let Some(report) = report {
+                let file = File::create(report).map_err(Error::IOError).unwrap();
+                match report_format {
+                    ReportFormat::Json => data_quality_report.write_json(file),
+                    ReportFormat::Csv => data_quality_report.write_csv(file),
                 }
-                None => buildings.estimate_population(),
+                .unwrap();
+            }
+
+            let mut rng = populator_config.rng();
+            match inhabitants {
+                Some(inhabitants) => buildings
+                    .distribute_population(*inhabitants, &populator_config, &mut rng)
+                    .unwrap(),
+                None => buildings
+                    .estimate_population(&populator_config, &mut rng)
+                    .unwrap(),
             }
             println!(
                 "Total Population: {}",
                 buildings.iter().map(|building| building.pop).sum::<u64>()
             );
-            let geojson = write_polygons_to_geojson(&buildings.into_inner());
+            let geojson = write_polygons_to_geojson(&buildings.into_inner(), *explain);
 
             // Create a temporary file.
             let temp_directory = PathBuf::from("./out/");
@@ -81,6 +184,13 @@ fn main() {
             let mut file = File::create(temp_file).unwrap();
             write!(file, "{geojson}").unwrap();
         }
+        Some(Commands::Explain { source, osm_id }) => {
+            let (buildings, _) = load_buildings(source, false, &populator_config);
+            match buildings.get(*osm_id) {
+                Some(building) => println!("{}", building.estimate),
+                None => println!("No building found for {osm_id:?}"),
+            }
+        }
         None => {}
     }
 }
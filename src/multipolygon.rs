@@ -0,0 +1,225 @@
+use geo::Contains;
+use geo::Coord;
+use geo::LineString;
+use geo::MultiPolygon;
+use geo::Polygon;
+use osmpbfreader::NodeId;
+use osmpbfreader::OsmId;
+use osmpbfreader::OsmObj;
+use osmpbfreader::Relation;
+use std::collections::BTreeMap;
+
+use crate::coordinates::referenced_coord;
+use crate::Error;
+
+/// Check if osm obj is a multipolygon relation
+pub(crate) fn is_multipolygon(obj: &OsmObj) -> bool {
+    obj.is_relation() && obj.tags().contains("type", "multipolygon")
+}
+
+/// Assembles the outer and inner members of a multipolygon relation into a geometry with holes.
+///
+/// Returns `None` if no closed outer ring can be built from the members found in `objs`.
+pub(crate) fn assemble_multipolygon(
+    relation: &Relation,
+    objs: &BTreeMap<OsmId, OsmObj>,
+) -> Result<Option<MultiPolygon>, Error> {
+    let mut outer_ways = vec![];
+    let mut inner_ways = vec![];
+    for member in &relation.refs {
+        let Some(OsmObj::Way(way)) = objs.get(&member.member) else {
+            continue;
+        };
+        match member.role.as_str() {
+            "inner" => inner_ways.push(way.nodes.clone()),
+            // Untagged members are treated as outer, as most renderers do
+            _ => outer_ways.push(way.nodes.clone()),
+        }
+    }
+
+    let outer_rings: Vec<LineString> = stitch_rings(outer_ways)
+        .iter()
+        .map(|ring| ring_to_line_string(relation, ring, objs))
+        .collect::<Result<_, _>>()?;
+    let inner_rings: Vec<LineString> = stitch_rings(inner_ways)
+        .iter()
+        .map(|ring| ring_to_line_string(relation, ring, objs))
+        .collect::<Result<_, _>>()?;
+    if outer_rings.is_empty() {
+        return Ok(None);
+    }
+
+    // Assign every inner ring to the first outer ring containing it
+    let mut polygons: Vec<Polygon> = outer_rings
+        .into_iter()
+        .map(|ring| Polygon::new(ring, vec![]))
+        .collect();
+    for inner_ring in inner_rings {
+        let Some(first_coord) = inner_ring.0.first().copied() else {
+            continue;
+        };
+        if let Some(polygon) = polygons
+            .iter_mut()
+            .find(|polygon| polygon.contains(&first_coord))
+        {
+            polygon.interiors_push(inner_ring);
+        }
+    }
+
+    Ok(Some(MultiPolygon::new(polygons)))
+}
+
+/// Joins way segments sharing end nodes into closed rings. Segments that cannot be closed are dropped.
+fn stitch_rings(mut segments: Vec<Vec<NodeId>>) -> Vec<Vec<NodeId>> {
+    let mut rings = vec![];
+    segments.retain(|segment| segment.len() >= 2);
+
+    while let Some(mut ring) = segments.pop() {
+        while ring.first() != ring.last() {
+            let end = *ring.last().unwrap();
+            let Some(position) = segments
+                .iter()
+                .position(|segment| segment.first() == Some(&end) || segment.last() == Some(&end))
+            else {
+                break;
+            };
+            let mut segment = segments.swap_remove(position);
+            if segment.first() != Some(&end) {
+                segment.reverse();
+            }
+            ring.extend(segment.into_iter().skip(1));
+        }
+
+        if ring.len() >= 4 && ring.first() == ring.last() {
+            rings.push(ring);
+        }
+    }
+
+    rings
+}
+
+fn ring_to_line_string(
+    relation: &Relation,
+    ring: &[NodeId],
+    objs: &BTreeMap<OsmId, OsmObj>,
+) -> Result<LineString, Error> {
+    ring.iter()
+        .map(|node_id| referenced_coord(OsmId::Relation(relation.id), *node_id, objs))
+        .collect::<Result<Vec<Coord>, Error>>()
+        .map(LineString::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coordinates::decimicro;
+    use geo::Area;
+    use osmpbfreader::{Node, Ref, RelationId, Way, WayId};
+
+    fn ids(ids: &[i64]) -> Vec<NodeId> {
+        ids.iter().map(|id| NodeId(*id)).collect()
+    }
+
+    #[test]
+    fn stitches_reversed_segments_and_drops_open_rings() {
+        let rings = stitch_rings(vec![ids(&[1, 2, 3]), ids(&[1, 4, 3])]);
+        assert_eq!(rings.len(), 1);
+        let mut ring = rings[0].clone();
+        assert_eq!(ring.first(), ring.last());
+        ring.sort();
+        assert_eq!(ring, ids(&[1, 1, 2, 3, 4]));
+
+        assert!(stitch_rings(vec![ids(&[1, 2, 3]), ids(&[3, 4, 5])]).is_empty());
+        assert!(stitch_rings(vec![ids(&[1, 2, 1]), ids(&[7])]).is_empty());
+        assert_eq!(
+            stitch_rings(vec![ids(&[1, 2, 3, 1]), ids(&[5, 6])]).len(),
+            1
+        );
+    }
+
+    /// Square of four nodes starting at `first_node` as a single closed way, `corner` as `(lon, lat)`
+    fn square(
+        objs: &mut BTreeMap<OsmId, OsmObj>,
+        way: i64,
+        first_node: i64,
+        corner: (f64, f64),
+        size: f64,
+    ) {
+        let (lon, lat) = corner;
+        for (offset, (x, y)) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)].iter().enumerate() {
+            let id = NodeId(first_node + offset as i64);
+            objs.insert(
+                OsmId::Node(id),
+                OsmObj::Node(Node {
+                    id,
+                    tags: Default::default(),
+                    decimicro_lat: decimicro(lat + y * size),
+                    decimicro_lon: decimicro(lon + x * size),
+                }),
+            );
+        }
+        let nodes = [0, 1, 2, 3, 0].map(|offset| NodeId(first_node + offset));
+        objs.insert(
+            OsmId::Way(WayId(way)),
+            OsmObj::Way(Way {
+                id: WayId(way),
+                tags: Default::default(),
+                nodes: nodes.to_vec(),
+            }),
+        );
+    }
+
+    fn relation(members: &[(i64, &str)]) -> Relation {
+        Relation {
+            id: RelationId(1),
+            tags: Default::default(),
+            refs: members
+                .iter()
+                .map(|(way, role)| Ref {
+                    member: OsmId::Way(WayId(*way)),
+                    role: (*role).into(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn assigns_inner_rings_to_containing_outer_rings() {
+        let mut objs = BTreeMap::new();
+        square(&mut objs, 10, 100, (0., 0.), 1.);
+        square(&mut objs, 11, 110, (5., 0.), 1.);
+        square(&mut objs, 12, 120, (5.25, 0.25), 0.5);
+
+        // Untagged members count as outer
+        let multi_polygon =
+            assemble_multipolygon(&relation(&[(10, "outer"), (11, ""), (12, "inner")]), &objs)
+                .unwrap()
+                .unwrap();
+        assert_eq!(multi_polygon.0.len(), 2);
+        let interiors: Vec<usize> = multi_polygon
+            .0
+            .iter()
+            .map(|polygon| polygon.interiors().len())
+            .collect();
+        assert_eq!(interiors.iter().sum::<usize>(), 1);
+        let with_hole = multi_polygon
+            .0
+            .iter()
+            .find(|polygon| !polygon.interiors().is_empty())
+            .unwrap();
+        assert!(with_hole.exterior().0.iter().all(|coord| coord.x >= 5.));
+        assert!((multi_polygon.unsigned_area() - 1.75).abs() < 1e-9);
+
+        assert_eq!(
+            assemble_multipolygon(&relation(&[(12, "inner")]), &objs).unwrap(),
+            None
+        );
+        assert!(matches!(
+            assemble_multipolygon(
+                &relation(&[(10, "outer")]),
+                &BTreeMap::from([(OsmId::Way(WayId(10)), objs[&OsmId::Way(WayId(10))].clone())])
+            ),
+            Err(Error::MissingNode { .. })
+        ));
+    }
+}
@@ -0,0 +1,361 @@
+use handlebars::Handlebars;
+use osmpbfreader::Node;
+use osmpbfreader::NodeId;
+use osmpbfreader::OsmId;
+use osmpbfreader::OsmObj;
+use osmpbfreader::Ref;
+use osmpbfreader::Relation;
+use osmpbfreader::RelationId;
+use osmpbfreader::Tags;
+use osmpbfreader::Way;
+use osmpbfreader::WayId;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::coordinates::decimicro;
+use crate::Error;
+
+const QUERY_TEMPLATE: &str = r#"[out:json][timeout:{{timeout}}];
+{{#if area_name}}area["name"="{{area_name}}"]->.searchArea;
+{{/if}}(
+  nwr["building"]{{filter}};
+  nwr["building:part"]{{filter}};
+  relation["type"="building"]{{filter}};
+  node["addr:housenumber"]{{filter}};
+  node["addr:flats"]{{filter}};
+  node["addr:unit"]{{filter}};
+  way["addr:interpolation"]{{filter}};
+  nwr["landuse"~"^({{landuse}})$"]{{filter}};
+{{#each exclude_tags}}  nwr["{{this}}"]{{../filter}};
+{{/each}}{{#each mixed_use_tags}}  node["{{this}}"]{{../filter}};
+{{/each}});
+(._;>;);
+out body;"#;
+
+/// Area to query from the overpass api
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverpassArea {
+    BoundingBox {
+        south: f64,
+        west: f64,
+        north: f64,
+        east: f64,
+    },
+    Name(String),
+}
+
+impl FromStr for OverpassArea {
+    type Err = Infallible;
+
+    /// Parses `south,west,north,east` as bounding box, anything else as area name
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let coords: Vec<f64> = s
+            .split(',')
+            .filter_map(|coord| coord.trim().parse().ok())
+            .collect();
+        match coords[..] {
+            [south, west, north, east] if s.split(',').count() == 4 => {
+                Ok(OverpassArea::BoundingBox {
+                    south,
+                    west,
+                    north,
+                    east,
+                })
+            }
+            _ => Ok(OverpassArea::Name(s.to_string())),
+        }
+    }
+}
+
+impl Display for OverpassArea {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverpassArea::BoundingBox {
+                south,
+                west,
+                north,
+                east,
+            } => write!(f, "{south},{west},{north},{east}"),
+            OverpassArea::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OverpassResponse {
+    elements: Vec<OverpassElement>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum OverpassElement {
+    Node {
+        id: i64,
+        lat: f64,
+        lon: f64,
+        #[serde(default)]
+        tags: BTreeMap<String, String>,
+    },
+    Way {
+        id: i64,
+        nodes: Vec<i64>,
+        #[serde(default)]
+        tags: BTreeMap<String, String>,
+    },
+    Relation {
+        id: i64,
+        members: Vec<OverpassMember>,
+        #[serde(default)]
+        tags: BTreeMap<String, String>,
+    },
+}
+
+#[derive(Deserialize)]
+struct OverpassMember {
+    #[serde(rename = "type")]
+    member_type: String,
+    #[serde(rename = "ref")]
+    member_ref: i64,
+    role: String,
+}
+
+impl From<OverpassElement> for OsmObj {
+    fn from(element: OverpassElement) -> Self {
+        match element {
+            OverpassElement::Node { id, lat, lon, tags } => OsmObj::Node(Node {
+                id: NodeId(id),
+                tags: to_tags(tags),
+                decimicro_lat: decimicro(lat),
+                decimicro_lon: decimicro(lon),
+            }),
+            OverpassElement::Way { id, nodes, tags } => OsmObj::Way(Way {
+                id: WayId(id),
+                tags: to_tags(tags),
+                nodes: nodes.into_iter().map(NodeId).collect(),
+            }),
+            OverpassElement::Relation { id, members, tags } => OsmObj::Relation(Relation {
+                id: RelationId(id),
+                tags: to_tags(tags),
+                refs: members
+                    .into_iter()
+                    .filter_map(|member| {
+                        let id = match member.member_type.as_str() {
+                            "node" => OsmId::Node(NodeId(member.member_ref)),
+                            "way" => OsmId::Way(WayId(member.member_ref)),
+                            "relation" => OsmId::Relation(RelationId(member.member_ref)),
+                            _ => return None,
+                        };
+                        Some(Ref {
+                            member: id,
+                            role: member.role.into(),
+                        })
+                    })
+                    .collect(),
+            }),
+        }
+    }
+}
+
+fn to_tags(tags: BTreeMap<String, String>) -> Tags {
+    tags.into_iter()
+        .map(|(key, value)| (key.into(), value.into()))
+        .collect()
+}
+
+/// Builds the overpass ql query for buildings, house numbers, exclude areas and mixed use pois
+pub(crate) fn build_query(area: &OverpassArea, config: &Config) -> Result<String, Error> {
+    let (area_name, filter) = match area {
+        OverpassArea::BoundingBox {
+            south,
+            west,
+            north,
+            east,
+        } => (None, format!("({south},{west},{north},{east})")),
+        OverpassArea::Name(name) => (Some(escape(name)), "(area.searchArea)".to_string()),
+    };
+    let landuse = config
+        .exclude_landuse
+        .iter()
+        .map(|landuse| escape(&escape_regex(landuse)))
+        .collect::<Vec<_>>()
+        .join("|");
+    let escape_all =
+        |values: &[String]| values.iter().map(|value| escape(value)).collect::<Vec<_>>();
+
+    let mut handlebars = Handlebars::new();
+    handlebars.register_escape_fn(handlebars::no_escape);
+    handlebars
+        .render_template(
+            QUERY_TEMPLATE,
+            &serde_json::json!({
+                "timeout": config.overpass_timeout,
+                "area_name": area_name,
+                "filter": filter,
+                "landuse": landuse,
+                "exclude_tags": escape_all(&config.exclude_tags),
+                "mixed_use_tags": escape_all(&config.mixed_use_tags),
+            }),
+        )
+        .map_err(Error::TemplateError)
+}
+
+/// Escapes a value for a double quoted overpass ql string, the template itself is rendered without escaping
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes the regex syntax of a value matched literally in a regex filter
+fn escape_regex(value: &str) -> String {
+    value
+        .chars()
+        .flat_map(|c| match c {
+            '.' | '^' | '$' | '|' | '(' | ')' | '[' | ']' | '{' | '}' | '*' | '+' | '?' | '\\' => {
+                vec!['\\', c]
+            }
+            c => vec![c],
+        })
+        .collect()
+}
+
+/// Queries all objects needed for the calculation inside the area from the overpass api
+pub(crate) fn load_objs(
+    area: &OverpassArea,
+    config: &Config,
+) -> Result<BTreeMap<OsmId, OsmObj>, Error> {
+    let query = build_query(area, config)?;
+    let response: OverpassResponse = reqwest::blocking::Client::new()
+        .post(&config.overpass_url)
+        .timeout(Duration::from_secs(config.overpass_timeout))
+        .form(&[("data", query)])
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.json())
+        .map_err(Error::OverpassError)?;
+
+    Ok(response
+        .elements
+        .into_iter()
+        .map(OsmObj::from)
+        .map(|obj| (obj.id(), obj))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bounding_boxes_and_names() {
+        let cases = [
+            (
+                "47.9,13.7,48.0,13.8",
+                OverpassArea::BoundingBox {
+                    south: 47.9,
+                    west: 13.7,
+                    north: 48.0,
+                    east: 13.8,
+                },
+            ),
+            (
+                " 47.9, 13.7 ,48,13.8 ",
+                OverpassArea::BoundingBox {
+                    south: 47.9,
+                    west: 13.7,
+                    north: 48.0,
+                    east: 13.8,
+                },
+            ),
+            ("Gmunden", OverpassArea::Name("Gmunden".to_string())),
+            (
+                "Gmunden, Austria",
+                OverpassArea::Name("Gmunden, Austria".to_string()),
+            ),
+            ("1,2,3", OverpassArea::Name("1,2,3".to_string())),
+            ("1,2,3,4,5", OverpassArea::Name("1,2,3,4,5".to_string())),
+            ("1,2,3,x", OverpassArea::Name("1,2,3,x".to_string())),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<OverpassArea>().unwrap(), expected, "{input}");
+        }
+    }
+
+    #[test]
+    fn builds_queries_with_escaped_values() {
+        let config = Config::builder()
+            .overpass_timeout(60)
+            .exclude_landuse(vec!["industrial".to_string(), "a.b".to_string()])
+            .exclude_tags(vec!["leisure".to_string(), "x\"y".to_string()])
+            .mixed_use_tags(vec!["shop".to_string()])
+            .build();
+
+        let area = OverpassArea::Name("Sankt \"Wolfgang\"".to_string());
+        let query = build_query(&area, &config).unwrap();
+        assert!(query.starts_with("[out:json][timeout:60];"));
+        assert!(query.contains(r#"area["name"="Sankt \"Wolfgang\""]->.searchArea;"#));
+        assert!(query.contains(r#"nwr["building"](area.searchArea);"#));
+        assert!(query.contains(r#"nwr["landuse"~"^(industrial|a\\.b)$"](area.searchArea);"#));
+        assert!(query.contains(r#"  nwr["leisure"](area.searchArea);"#));
+        assert!(query.contains(r#"  nwr["x\"y"](area.searchArea);"#));
+        assert!(query.contains(r#"  node["shop"](area.searchArea);"#));
+
+        let area = "47.9,13.7,48,13.8".parse().unwrap();
+        let query = build_query(&area, &config).unwrap();
+        assert!(!query.contains("searchArea"));
+        assert!(query.contains(r#"node["addr:housenumber"](47.9,13.7,48,13.8);"#));
+    }
+
+    #[test]
+    fn converts_json_elements_to_osm_objs() {
+        let response: OverpassResponse = serde_json::from_str(
+            r#"{"elements": [
+                {"type": "node", "id": 1, "lat": 47.9180, "lon": 13.7990},
+                {"type": "node", "id": 2, "lat": 47.9185, "lon": 13.7995, "tags": {"addr:housenumber": "3"}},
+                {"type": "way", "id": 10, "nodes": [1, 2, 1], "tags": {"building": "yes"}},
+                {"type": "relation", "id": 20, "members": [
+                    {"type": "way", "ref": 10, "role": "outer"},
+                    {"type": "node", "ref": 2, "role": ""},
+                    {"type": "area", "ref": 3, "role": "outer"}
+                ], "tags": {"type": "multipolygon"}}
+            ]}"#,
+        )
+        .unwrap();
+        let objs: Vec<OsmObj> = response.elements.into_iter().map(OsmObj::from).collect();
+
+        let OsmObj::Node(node) = &objs[0] else {
+            panic!("expected node");
+        };
+        assert_eq!(
+            (node.decimicro_lat, node.decimicro_lon),
+            (479180000, 137990000)
+        );
+        assert!(node.tags.is_empty());
+        assert_eq!(objs[1].tags().get("addr:housenumber").unwrap(), "3");
+
+        let OsmObj::Way(way) = &objs[2] else {
+            panic!("expected way");
+        };
+        assert_eq!(way.nodes, [NodeId(1), NodeId(2), NodeId(1)]);
+        assert!(way.tags.contains("building", "yes"));
+
+        let OsmObj::Relation(relation) = &objs[3] else {
+            panic!("expected relation");
+        };
+        let members: Vec<(OsmId, &str)> = relation
+            .refs
+            .iter()
+            .map(|member| (member.member, member.role.as_str()))
+            .collect();
+        assert_eq!(
+            members,
+            [
+                (OsmId::Way(WayId(10)), "outer"),
+                (OsmId::Node(NodeId(2)), "")
+            ]
+        );
+    }
+}
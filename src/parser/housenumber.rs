@@ -1,33 +1,179 @@
+//! Parsing of house number lists like `addr:housenumber=2-6;7a` or `addr:flats=1-12`
+//!
+//! Lists are expanded into their single house numbers, each a number with an optional lowercase suffix like
+//! `a`, `bis`, `½` or a sub number `/1`. The canonical form of a list are its house numbers in numeric order,
+//! separated by `;`.
+//!
+//! ```
+//! use openhousepopulator::housenumber::{HouseNumberList, Locale, RangeMode};
+//!
+//! let list = HouseNumberList::parse("Nr. 2-6, 7 A", RangeMode::Inferred, &[Locale::De]).unwrap();
+//! assert_eq!(list.count(), 4);
+//! assert_eq!(list.to_string(), "2;4;6;7a");
+//! assert_eq!(list.to_string().parse::<HouseNumberList>().unwrap(), list);
+//! assert_eq!(list.iter().map(|number| number.number()).max(), Some(7));
+//! ```
+
 use itertools::Itertools;
 use nom::{
     branch::alt,
-    character::complete::{alpha0, alpha1, char, digit1, multispace0},
-    combinator::complete,
+    bytes::complete::take_while1,
+    character::complete::{char, digit1, multispace0, satisfy},
+    combinator::{complete, map_res, opt, verify},
     multi::separated_list1,
-    sequence::{delimited, pair, separated_pair},
+    sequence::{delimited, preceded, separated_pair},
     IResult,
 };
-use std::{collections::HashSet, error, fmt::Display};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, error, fmt::Display, str::FromStr};
+
+/// Ranges spanning more numbers are considered broken and rejected
+const MAX_RANGE: u32 = 1000;
+
+/// How the numbers between the ends of a range like `2-8` are expanded
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RangeMode {
+    /// Ranges with ends of the same parity contain every second number, as on one side of a street
+    /// (`2-8` is 2, 4, 6 and 8), other ranges every number
+    #[default]
+    Inferred,
+    /// Ranges contain every number, e.g. for flat numbers
+    All,
+}
+
+/// Locale specific extensions of the house number grammar
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    /// `Nr. 5`, `10 und 12`
+    De,
+    /// `No. 5`, `10 and 12`
+    En,
+    /// `n° 5`, `10 et 12`
+    Fr,
+    /// `n. 5`, `10 e 12`
+    It,
+    /// `nº 5`, `10 y 12`
+    Es,
+    /// `7/1` as one number with a sub number
+    Cz,
+    /// `д. 5`, `10 и 12`, `7/1` as one number with a sub number
+    Ru,
+}
+
+impl Locale {
+    /// Prefixes in front of the number, compared case-insensitively
+    fn prefixes(self) -> &'static [&'static str] {
+        match self {
+            Locale::De => &["nr.", "nr"],
+            Locale::En => &["no.", "no", "#"],
+            Locale::Fr => &["n°", "no"],
+            Locale::It => &["n.", "n°", "civico"],
+            Locale::Es => &["nº", "n°", "no."],
+            Locale::Cz => &["č.p.", "č."],
+            Locale::Ru => &["д.", "дом"],
+        }
+    }
+
+    /// Words separating the numbers of a list, compared case-insensitively
+    fn separators(self) -> &'static [&'static str] {
+        match self {
+            Locale::De => &["und", "u."],
+            Locale::En => &["and", "&"],
+            Locale::Fr => &["et"],
+            Locale::It => &["e"],
+            Locale::Es => &["y"],
+            // `a` would clash with letter suffixes like `5 a`
+            Locale::Cz => &[],
+            Locale::Ru => &["и"],
+        }
+    }
 
+    /// If a slash separates a sub number instead of list elements
+    fn slash_subnumbers(self) -> bool {
+        matches!(self, Locale::Cz | Locale::Ru)
+    }
+}
+
+/// House number as written, before ranges and subdivisions are expanded
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub enum HouseNumber {
+enum HouseNumber {
     Single(SingleHouseNumber),
     Subdivided(Subdivisions),
     Range(SingleHouseNumber, SingleHouseNumber),
 }
 
 impl HouseNumber {
-    fn singles(&self) -> Vec<SingleHouseNumber> {
+    /// Expands the house number, `None` for ranges too wide to be real
+    fn singles(&self, mode: RangeMode) -> Option<Vec<SingleHouseNumber>> {
         match self {
-            HouseNumber::Single(hn) => vec![hn.to_owned()],
-            HouseNumber::Subdivided(sdv) => sdv.singles(),
-            HouseNumber::Range(hn1, hn2) => (hn1.0..hn2.0)
-                .map(|e| SingleHouseNumber(e, String::new()))
-                .collect(),
+            HouseNumber::Single(hn) => Some(vec![hn.to_owned()]),
+            HouseNumber::Subdivided(sdv) => Some(sdv.singles()),
+            HouseNumber::Range(hn1, hn2) => range_singles(hn1, hn2, mode),
         }
     }
 }
 
+/// Expands an inclusive range, in either direction. Ranges of the same number expand their letters (`3a-3d`).
+fn range_singles(
+    hn1: &SingleHouseNumber,
+    hn2: &SingleHouseNumber,
+    mode: RangeMode,
+) -> Option<Vec<SingleHouseNumber>> {
+    let (start, end) = if hn1 <= hn2 { (hn1, hn2) } else { (hn2, hn1) };
+    if start.0 == end.0 {
+        return Some(letter_range(start, end));
+    }
+    if end.0 - start.0 > MAX_RANGE {
+        return None;
+    }
+
+    let step = match mode {
+        RangeMode::Inferred if start.0 % 2 == end.0 % 2 => 2,
+        _ => 1,
+    };
+    Some(
+        (start.0..=end.0)
+            .step_by(step)
+            .map(|number| match number {
+                number if number == start.0 => start.clone(),
+                number if number == end.0 => end.clone(),
+                number => SingleHouseNumber(number, String::new()),
+            })
+            .collect(),
+    )
+}
+
+/// Expands the letters of a range of the same number, a missing letter comes before `a` (`3-3b` is 3, 3a and 3b)
+fn letter_range(start: &SingleHouseNumber, end: &SingleHouseNumber) -> Vec<SingleHouseNumber> {
+    let (Some(first), Some(last)) = (letter_index(&start.1), letter_index(&end.1)) else {
+        return vec![start.clone(), end.clone()];
+    };
+    let (first, last) = (first.min(last), first.max(last));
+    (first..=last)
+        .map(|index| {
+            let letter = match index {
+                0 => String::new(),
+                index => ((b'a' + index - 1) as char).to_string(),
+            };
+            SingleHouseNumber(start.0, letter)
+        })
+        .collect()
+}
+
+/// Gets the position of a single letter suffix in the alphabet starting at 1, 0 for no suffix
+pub(crate) fn letter_index(suffix: &str) -> Option<u8> {
+    let mut chars = suffix.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Some(0),
+        (Some(letter), None) if letter.is_ascii_alphabetic() => {
+            Some(letter.to_ascii_lowercase() as u8 - b'a' + 1)
+        }
+        _ => None,
+    }
+}
+
 impl Display for HouseNumber {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -38,8 +184,26 @@ impl Display for HouseNumber {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
-pub struct SingleHouseNumber(u16, String);
+/// Single house number like `12`, `12a` or `12bis`, serialized in its canonical form
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct SingleHouseNumber(u32, String);
+
+impl SingleHouseNumber {
+    /// Creates a house number, normalizing the suffix to lowercase
+    pub fn new(number: u32, suffix: &str) -> SingleHouseNumber {
+        SingleHouseNumber(number, suffix.to_lowercase())
+    }
+
+    pub fn number(&self) -> u32 {
+        self.0
+    }
+
+    /// Suffix following the number, e.g. `a`, `bis`, `½` or `/1`, empty for plain numbers
+    pub fn suffix(&self) -> &str {
+        &self.1
+    }
+}
 
 impl Display for SingleHouseNumber {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -47,14 +211,45 @@ impl Display for SingleHouseNumber {
     }
 }
 
+/// Parses the canonical form, the number followed by a suffix of an optional fraction, letters and sub number
+impl FromStr for SingleHouseNumber {
+    type Err = ParseError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (rest, number) =
+            number(input.trim()).map_err(|err| ParseError::from_nom(input, err))?;
+        if !is_canonical_suffix(rest) {
+            return Err(ParseError::NotFullyConsumedError {
+                input: input.to_string(),
+                rest: rest.to_string(),
+            });
+        }
+        Ok(SingleHouseNumber::new(number, rest))
+    }
+}
+
+impl From<SingleHouseNumber> for String {
+    fn from(housenumber: SingleHouseNumber) -> Self {
+        housenumber.to_string()
+    }
+}
+
+impl TryFrom<String> for SingleHouseNumber {
+    type Error = ParseError;
+
+    fn try_from(input: String) -> Result<Self, Self::Error> {
+        input.parse()
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct Subdivisions(u16, Vec<String>);
+struct Subdivisions(u32, Vec<String>);
 
 impl Subdivisions {
     fn singles(&self) -> Vec<SingleHouseNumber> {
         self.1
             .iter()
-            .map(|e| SingleHouseNumber(self.0, e.clone()))
+            .map(|e| SingleHouseNumber::new(self.0, e))
             .collect()
     }
 }
@@ -65,115 +260,310 @@ impl Display for Subdivisions {
     }
 }
 
-#[derive(Default, Debug)]
-pub struct HouseNumberList(HashSet<SingleHouseNumber>);
+/// Distinct single house numbers of a list, ordered by number and suffix. Serialized as a sequence of
+/// canonical house numbers.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct HouseNumberList(BTreeSet<SingleHouseNumber>);
 
 impl HouseNumberList {
+    /// Parses a list of house numbers with the grammar extensions of the locales, expanding ranges by the given mode
+    pub fn parse(input: &str, mode: RangeMode, locales: &[Locale]) -> Result<Self, ParseError> {
+        let (rest, list) =
+            housenumber_list(input, locales).map_err(|err| ParseError::from_nom(input, err))?;
+        if !rest.is_empty() {
+            return Err(ParseError::NotFullyConsumedError {
+                input: input.to_string(),
+                rest: rest.to_string(),
+            });
+        }
+        let mut numbers = BTreeSet::new();
+        for housenumber in &list {
+            let Some(singles) = housenumber.singles(mode) else {
+                return Err(ParseError::RangeTooLargeError {
+                    input: input.to_string(),
+                    range: housenumber.to_string(),
+                });
+            };
+            numbers.extend(singles);
+        }
+        Ok(HouseNumberList(numbers))
+    }
+
     pub fn merge(&mut self, other: HouseNumberList) {
         self.0.extend(other.0);
     }
-}
 
-impl HouseNumberList {
     pub fn count(&self) -> usize {
         self.0.len()
     }
+
+    /// Iterates the single house numbers in order
+    pub fn iter(&self) -> impl Iterator<Item = &SingleHouseNumber> {
+        self.0.iter()
+    }
 }
 
+impl IntoIterator for HouseNumberList {
+    type Item = SingleHouseNumber;
+    type IntoIter = std::collections::btree_set::IntoIter<SingleHouseNumber>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a HouseNumberList {
+    type Item = &'a SingleHouseNumber;
+    type IntoIter = std::collections::btree_set::Iter<'a, SingleHouseNumber>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl FromIterator<SingleHouseNumber> for HouseNumberList {
+    fn from_iter<T: IntoIterator<Item = SingleHouseNumber>>(iter: T) -> Self {
+        HouseNumberList(iter.into_iter().collect())
+    }
+}
+
+/// Canonical form, the house numbers in order separated by `;`
 impl Display for HouseNumberList {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            self.0.iter().map(|e| e.to_string()).sorted().join("/")
-        )
+        write!(f, "{}", self.0.iter().join(";"))
     }
 }
 
-impl<'a> TryFrom<&'a str> for HouseNumberList {
-    type Error = ParseError<'a>;
+/// Parses with the default range mode and without locale extensions, like
+/// `HouseNumberList::parse(input, RangeMode::default(), &[])`. The canonical form reads back as is, except for
+/// sub numbers like `7/1`, which need a locale with slash sub numbers.
+impl FromStr for HouseNumberList {
+    type Err = ParseError;
 
-    fn try_from(input: &'a str) -> Result<Self, Self::Error> {
-        let (rest, value) = housenumber_list(input).map_err(ParseError::NomError)?;
-        if !rest.is_empty() {
-            return Err(ParseError::NotFullyConsumedError(input, rest));
-        }
-        Ok(value)
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        HouseNumberList::parse(input, RangeMode::default(), &[])
+    }
+}
+
+impl TryFrom<&str> for HouseNumberList {
+    type Error = ParseError;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        input.parse()
     }
 }
 
-#[derive(Debug)]
-pub enum ParseError<'a> {
-    NotFullyConsumedError(&'a str, &'a str),
-    NomError(nom::Err<nom::error::Error<&'a str>>),
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// Nothing could be parsed from `rest` on
+    InvalidError { input: String, rest: String },
+    /// The start of the input was parsed, but `rest` is left over
+    NotFullyConsumedError { input: String, rest: String },
+    /// The input contains a range spanning more than 1000 numbers
+    RangeTooLargeError { input: String, range: String },
+}
+
+impl ParseError {
+    pub(crate) fn from_nom(input: &str, err: nom::Err<nom::error::Error<&str>>) -> ParseError {
+        let rest = match err {
+            nom::Err::Error(err) | nom::Err::Failure(err) => err.input,
+            nom::Err::Incomplete(_) => "",
+        };
+        ParseError::InvalidError {
+            input: input.to_string(),
+            rest: rest.to_string(),
+        }
+    }
 }
 
-impl<'a> error::Error for ParseError<'a> {}
-impl<'a> Display for ParseError<'a> {
+impl error::Error for ParseError {}
+impl Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ParseError::NotFullyConsumedError(input, rest) => {
+            ParseError::InvalidError { input, rest } => {
+                write!(f, "Parsing failed for string {input} at {rest}")
+            }
+            ParseError::RangeTooLargeError { input, range } => {
+                write!(
+                    f,
+                    "Parsing failed for string {input}, range {range} is too large"
+                )
+            }
+            ParseError::NotFullyConsumedError { input, rest } => {
                 write!(f, "Parsing failed for string {input}, with rest {rest}")
             }
-            ParseError::NomError(err) => write!(f, "Parsing failed with NomError: {err}"),
         }
     }
 }
 
-fn house_number(input: &str) -> IResult<&str, HouseNumber> {
-    alt((complete(housenumber_range), wrapped_housenumber))(input)
+fn house_number<'a>(input: &'a str, locales: &[Locale]) -> IResult<&'a str, HouseNumber> {
+    preceded(
+        opt(|input| prefix(input, locales)),
+        alt((
+            complete(|input| housenumber_range(input, locales)),
+            |input| wrapped_housenumber(input, locales),
+        )),
+    )(input)
 }
 
-fn wrapped_housenumber(input: &str) -> IResult<&str, HouseNumber> {
-    alt((wrapped_subdivided_housenumber, wrapped_single_housenumber))(input)
+fn wrapped_housenumber<'a>(input: &'a str, locales: &[Locale]) -> IResult<&'a str, HouseNumber> {
+    alt((
+        |input| wrapped_subdivided_housenumber(input, locales),
+        |input| wrapped_single_housenumber(input, locales),
+    ))(input)
 }
 
-fn wrapped_single_housenumber(input: &str) -> IResult<&str, HouseNumber> {
-    let (rest, housenumber) = single_housenumber(input)?;
+fn wrapped_single_housenumber<'a>(
+    input: &'a str,
+    locales: &[Locale],
+) -> IResult<&'a str, HouseNumber> {
+    let (rest, housenumber) = single_housenumber(input, locales)?;
     Ok((rest, HouseNumber::Single(housenumber)))
 }
 
-fn wrapped_subdivided_housenumber(input: &str) -> IResult<&str, HouseNumber> {
-    let (rest, housenumber) = subdivided_housenumber(input)?;
+fn wrapped_subdivided_housenumber<'a>(
+    input: &'a str,
+    locales: &[Locale],
+) -> IResult<&'a str, HouseNumber> {
+    let (rest, housenumber) = subdivided_housenumber(input, locales)?;
     Ok((rest, HouseNumber::Subdivided(housenumber)))
 }
 
-fn subdivided_housenumber(input: &str) -> IResult<&str, Subdivisions> {
-    let (rest, (number, letters)) =
-        pair(digit1, ws(separated_list1(list_delimiter, alpha1)))(input)?;
+fn subdivided_housenumber<'a>(
+    input: &'a str,
+    locales: &[Locale],
+) -> IResult<&'a str, Subdivisions> {
+    let (rest, number) = number(input)?;
+    // A single letter is left to the single house number, which may continue with a sub number (`12a/1`)
+    let (rest, letters) = verify(
+        ws(separated_list1(
+            |input| list_delimiter(input, locales),
+            verify(take_while1(char::is_alphabetic), |letters: &str| {
+                !is_separator(letters, locales)
+            }),
+        )),
+        |letters: &Vec<&str>| letters.len() > 1,
+    )(rest)?;
     Ok((
         rest,
-        Subdivisions(
-            number.parse().unwrap_or_default(),
-            letters.iter().map(|e| e.to_string()).collect(),
-        ),
+        Subdivisions(number, letters.iter().map(|e| e.to_string()).collect()),
     ))
 }
 
-fn single_housenumber(input: &str) -> IResult<&str, SingleHouseNumber> {
-    let (rest, (number, letter)) = pair(digit1, ws(alpha0))(input)?;
-    Ok((
-        rest,
-        SingleHouseNumber(number.parse().unwrap_or_default(), letter.to_string()),
-    ))
+/// A number followed by an optional fraction (`12½`), letters (`12a`, `12 bis`, `12ä`)
+/// and a sub number in locales using slashes for them (`7/1`)
+fn single_housenumber<'a>(
+    input: &'a str,
+    locales: &[Locale],
+) -> IResult<&'a str, SingleHouseNumber> {
+    let (rest, number) = number(input)?;
+    let (rest, fraction) = opt(preceded(multispace0, satisfy(is_fraction)))(rest)?;
+    let (rest, space) = multispace0(rest)?;
+    // Separator words are only letters if attached to the number, e.g. `12e` but not `12 e 14`
+    let (rest, letters) = opt(verify(take_while1(char::is_alphabetic), |letters: &str| {
+        space.is_empty() || !is_separator(letters, locales)
+    }))(rest)?;
+    let (rest, _) = multispace0(rest)?;
+    let (rest, subnumber) = if locales.iter().any(|locale| locale.slash_subnumbers()) {
+        opt(preceded(ws(char('/')), digit1))(rest)?
+    } else {
+        (rest, None)
+    };
+
+    let mut suffix: String = fraction.into_iter().collect();
+    suffix.push_str(letters.unwrap_or_default());
+    if let Some(subnumber) = subnumber {
+        suffix.push('/');
+        suffix.push_str(subnumber);
+    }
+    Ok((rest, SingleHouseNumber::new(number, &suffix)))
+}
+
+/// Digits of a house number, failing for numbers out of range instead of counting them as zero
+fn number(input: &str) -> IResult<&str, u32> {
+    map_res(digit1, str::parse::<u32>)(input)
 }
 
-fn housenumber_range(input: &str) -> IResult<&str, HouseNumber> {
-    let (rest, (housenumber1, housenumber2)) =
-        separated_pair(single_housenumber, ws(char('-')), single_housenumber)(input)?;
+fn is_canonical_suffix(suffix: &str) -> bool {
+    let suffix = suffix.strip_prefix(is_fraction).unwrap_or(suffix);
+    let suffix = suffix.trim_start_matches(char::is_alphabetic);
+    match suffix.strip_prefix('/') {
+        Some(subnumber) => !subnumber.is_empty() && subnumber.chars().all(|c| c.is_ascii_digit()),
+        None => suffix.is_empty(),
+    }
+}
+
+fn is_fraction(c: char) -> bool {
+    matches!(c, '½' | '¼' | '¾' | '⅓' | '⅔')
+}
+
+fn is_separator(word: &str, locales: &[Locale]) -> bool {
+    let word = word.to_lowercase();
+    locales
+        .iter()
+        .flat_map(|locale| locale.separators())
+        .any(|separator| *separator == word)
+}
+
+/// Skips a locale specific prefix like `Nr.` in front of a number
+fn prefix<'a>(input: &'a str, locales: &[Locale]) -> IResult<&'a str, ()> {
+    for prefix in locales.iter().flat_map(|locale| locale.prefixes()) {
+        let Some(head) = input.get(..prefix.len()) else {
+            continue;
+        };
+        let rest = &input[prefix.len()..];
+        if head.to_lowercase() == *prefix
+            && rest.starts_with(|c: char| c.is_whitespace() || c.is_ascii_digit())
+        {
+            let (rest, _) = multispace0(rest)?;
+            return Ok((rest, ()));
+        }
+    }
+    Err(nom::Err::Error(nom::error::Error::new(
+        input,
+        nom::error::ErrorKind::Tag,
+    )))
+}
+
+fn housenumber_range<'a>(input: &'a str, locales: &[Locale]) -> IResult<&'a str, HouseNumber> {
+    let (rest, (housenumber1, housenumber2)) = separated_pair(
+        |input| single_housenumber(input, locales),
+        ws(char('-')),
+        |input| single_housenumber(input, locales),
+    )(input)?;
     Ok((rest, HouseNumber::Range(housenumber1, housenumber2)))
 }
 
-fn housenumber_list(input: &str) -> IResult<&str, HouseNumberList> {
-    let (rest, list) = separated_list1(list_delimiter, house_number)(input)?;
-    Ok((
-        rest,
-        HouseNumberList(list.into_iter().flat_map(|e| e.singles()).collect()),
-    ))
+fn housenumber_list<'a>(input: &'a str, locales: &[Locale]) -> IResult<&'a str, Vec<HouseNumber>> {
+    separated_list1(
+        |input| list_delimiter(input, locales),
+        |input| house_number(input, locales),
+    )(input)
 }
 
-fn list_delimiter(input: &str) -> IResult<&str, char> {
-    alt((ws(char('/')), ws(char(',')), ws(char(';'))))(input)
+/// Commas, semicolons, slashes (unless used for sub numbers) and locale specific words like `et`
+fn list_delimiter<'a>(input: &'a str, locales: &[Locale]) -> IResult<&'a str, ()> {
+    let (rest, _) = multispace0(input)?;
+    let slash = !locales.iter().any(|locale| locale.slash_subnumbers());
+    if rest.starts_with([',', ';']) || (slash && rest.starts_with('/')) {
+        let (rest, _) = multispace0(&rest[1..])?;
+        return Ok((rest, ()));
+    }
+    for separator in locales.iter().flat_map(|locale| locale.separators()) {
+        let Some(head) = rest.get(..separator.len()) else {
+            continue;
+        };
+        let after = &rest[separator.len()..];
+        if head.to_lowercase() == *separator && after.starts_with(char::is_whitespace) {
+            let (after, _) = multispace0(after)?;
+            return Ok((after, ()));
+        }
+    }
+    Err(nom::Err::Error(nom::error::Error::new(
+        input,
+        nom::error::ErrorKind::Char,
+    )))
 }
 
 fn ws<'a, F, O, E: nom::error::ParseError<&'a str>>(
@@ -184,3 +574,219 @@ where
 {
     delimited(multispace0, inner, multispace0)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(input: &str, mode: RangeMode, locales: &[Locale]) -> Vec<String> {
+        let list = HouseNumberList::parse(input, mode, locales).unwrap();
+        list.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn expands_real_world_house_numbers() {
+        let cases: &[(&str, RangeMode, &[&str])] = &[
+            ("12", RangeMode::Inferred, &["12"]),
+            ("12a", RangeMode::Inferred, &["12a"]),
+            ("12 a", RangeMode::Inferred, &["12a"]),
+            ("12;14", RangeMode::Inferred, &["12", "14"]),
+            ("12, 14", RangeMode::Inferred, &["12", "14"]),
+            ("12/14", RangeMode::Inferred, &["12", "14"]),
+            ("5a/b", RangeMode::Inferred, &["5a", "5b"]),
+            ("12;12", RangeMode::Inferred, &["12"]),
+            ("2-8", RangeMode::Inferred, &["2", "4", "6", "8"]),
+            ("1-7", RangeMode::Inferred, &["1", "3", "5", "7"]),
+            ("2 - 8", RangeMode::Inferred, &["2", "4", "6", "8"]),
+            ("8-2", RangeMode::Inferred, &["2", "4", "6", "8"]),
+            ("2-5", RangeMode::Inferred, &["2", "3", "4", "5"]),
+            ("2-8", RangeMode::All, &["2", "3", "4", "5", "6", "7", "8"]),
+            ("1-4", RangeMode::All, &["1", "2", "3", "4"]),
+            ("3a-3d", RangeMode::Inferred, &["3a", "3b", "3c", "3d"]),
+            ("3A-3C", RangeMode::Inferred, &["3a", "3b", "3c"]),
+            ("3-3c", RangeMode::Inferred, &["3", "3a", "3b", "3c"]),
+            ("3d-3a", RangeMode::Inferred, &["3a", "3b", "3c", "3d"]),
+            ("3a-7", RangeMode::Inferred, &["3a", "5", "7"]),
+            ("1-3;7", RangeMode::Inferred, &["1", "3", "7"]),
+            ("1-3;7", RangeMode::All, &["1", "2", "3", "7"]),
+        ];
+
+        for (input, mode, expected) in cases {
+            assert_eq!(&expand(input, *mode, &[]), expected, "{input} ({mode:?})");
+            assert_eq!(
+                HouseNumberList::parse(input, *mode, &[]).unwrap().count(),
+                expected.len(),
+                "{input} ({mode:?})"
+            );
+        }
+    }
+
+    #[test]
+    fn expands_international_house_numbers() {
+        let cases: &[(&str, &[Locale], &[&str])] = &[
+            ("12 bis", &[], &["12bis"]),
+            ("12½", &[], &["12½"]),
+            ("12 ½", &[], &["12½"]),
+            ("5 A", &[], &["5a"]),
+            ("12ä", &[], &["12ä"]),
+            ("123456", &[], &["123456"]),
+            ("7/1", &[], &["1", "7"]),
+            ("7/1", &[Locale::Cz], &["7/1"]),
+            ("7/1, 7/2", &[Locale::Ru], &["7/1", "7/2"]),
+            ("12a/1", &[Locale::Cz], &["12a/1"]),
+            ("12 a/1, 12b", &[Locale::Ru], &["12a/1", "12b"]),
+            ("5a,b", &[Locale::Cz], &["5a", "5b"]),
+            ("Nr. 5", &[Locale::De], &["5"]),
+            ("nr 5-7", &[Locale::De], &["5", "7"]),
+            ("10 und 12", &[Locale::De], &["10", "12"]),
+            ("No. 5", &[Locale::En], &["5"]),
+            ("n° 5", &[Locale::Fr], &["5"]),
+            ("10 et 12", &[Locale::Fr], &["10", "12"]),
+            ("12 bis et 14", &[Locale::Fr], &["12bis", "14"]),
+            ("10 e 12", &[Locale::It], &["10", "12"]),
+            ("12e", &[Locale::It], &["12e"]),
+            ("10 y 12", &[Locale::Es], &["10", "12"]),
+            ("д. 5", &[Locale::Ru], &["5"]),
+            ("10 и 12", &[Locale::Ru], &["10", "12"]),
+            ("10 et 12", &[Locale::De, Locale::Fr], &["10", "12"]),
+        ];
+
+        for (input, locales, expected) in cases {
+            assert_eq!(
+                &expand(input, RangeMode::Inferred, locales),
+                expected,
+                "{input} ({locales:?})"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_forms() {
+        let cases: &[(&str, &[Locale])] = &[
+            ("10 et 12", &[]),
+            ("Nr. 5", &[]),
+            ("10 und 12", &[Locale::Fr]),
+            ("99999999999", &[]),
+            ("s/n", &[Locale::Es]),
+        ];
+        for (input, locales) in cases {
+            assert!(
+                HouseNumberList::parse(input, RangeMode::Inferred, locales).is_err(),
+                "{input} ({locales:?}) should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_strings_without_locale_extensions() {
+        for input in [
+            "12/14",
+            "7/1",
+            "7/1;7/3",
+            "2-6;8a",
+            "2-6, 8a",
+            "10 und 12",
+            "Nr. 5",
+            "12½",
+            "5a;5A",
+            "1-2000",
+            "12#",
+        ] {
+            assert_eq!(
+                input.parse::<HouseNumberList>(),
+                HouseNumberList::parse(input, RangeMode::default(), &[]),
+                "{input}"
+            );
+        }
+        let list: HouseNumberList = "12/14".parse().unwrap();
+        assert_eq!(list.to_string(), "12;14");
+    }
+
+    #[test]
+    fn round_trips_canonical_form() {
+        let list = HouseNumberList::parse(
+            "Nr. 7/1, 7/3, 2 A, 12½",
+            RangeMode::Inferred,
+            &[Locale::De, Locale::Cz],
+        )
+        .unwrap();
+        assert_eq!(list.to_string(), "2a;7/1;7/3;12½");
+        let numbers: Vec<(u32, &str)> = list.iter().map(|e| (e.number(), e.suffix())).collect();
+        assert_eq!(numbers, [(2, "a"), (7, "/1"), (7, "/3"), (12, "½")]);
+        // Sub numbers are read back by a slash locale only
+        let reparsed =
+            HouseNumberList::parse(&list.to_string(), RangeMode::Inferred, &[Locale::Cz]);
+        assert_eq!(reparsed.unwrap(), list);
+
+        let list = HouseNumberList::parse("7/1, 7/2", RangeMode::Inferred, &[Locale::Cz]).unwrap();
+        assert_eq!(list.to_string(), "7/1;7/2");
+        let reparsed =
+            HouseNumberList::parse(&list.to_string(), RangeMode::Inferred, &[Locale::Cz]);
+        assert_eq!(reparsed.unwrap(), list);
+
+        let list = HouseNumberList::parse("2 A, 12½, 3bis", RangeMode::Inferred, &[]).unwrap();
+        assert_eq!(list.to_string().parse::<HouseNumberList>().unwrap(), list);
+
+        let list: HouseNumberList = "5a;5A;5 A".parse().unwrap();
+        assert_eq!((list.count(), list.to_string()), (1, "5a".to_string()));
+        assert_eq!(SingleHouseNumber::new(5, "A"), "5a".parse().unwrap());
+
+        let list = HouseNumberList::parse("2 A, 7/1, 7/3, 12½", RangeMode::Inferred, &[Locale::Cz])
+            .unwrap();
+        let json = serde_json::to_string(&list).unwrap();
+        assert_eq!(json, r#"["2a","7/1","7/3","12½"]"#);
+        assert_eq!(
+            serde_json::from_str::<HouseNumberList>(&json).unwrap(),
+            list
+        );
+        assert!(serde_json::from_str::<HouseNumberList>(r#"["2 A"]"#).is_err());
+
+        let reparsed: HouseNumberList = "2-6;8a".parse().unwrap();
+        assert_eq!(
+            reparsed.to_string().parse::<HouseNumberList>().unwrap(),
+            reparsed
+        );
+        assert_eq!(
+            reparsed.into_iter().next_back(),
+            Some(SingleHouseNumber::new(8, "a"))
+        );
+    }
+
+    #[test]
+    fn reports_owned_errors() {
+        let error = {
+            let input = String::from("12 #");
+            HouseNumberList::try_from(input.as_str()).unwrap_err()
+        };
+        assert_eq!(
+            error,
+            ParseError::NotFullyConsumedError {
+                input: "12 #".to_string(),
+                rest: "#".to_string()
+            }
+        );
+        assert!(matches!(
+            "abc".parse::<HouseNumberList>(),
+            Err(ParseError::InvalidError { .. })
+        ));
+
+        assert_eq!("1-1001".parse::<HouseNumberList>().unwrap().count(), 501);
+        assert_eq!(
+            "2;1-5000".parse::<HouseNumberList>().unwrap_err(),
+            ParseError::RangeTooLargeError {
+                input: "2;1-5000".to_string(),
+                range: "1-5000".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_house_numbers() {
+        for input in ["", "abc", "12-", "-12", "12 ;", "12#"] {
+            assert!(
+                HouseNumberList::try_from(input).is_err(),
+                "{input} should not parse"
+            );
+        }
+    }
+}
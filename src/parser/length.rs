@@ -0,0 +1,111 @@
+use nom::{
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::{char, multispace0},
+    combinator::{map, opt, value, verify},
+    number::complete::double,
+    sequence::{delimited, pair, preceded, terminated},
+    IResult,
+};
+
+use crate::parser::housenumber::ParseError;
+
+/// A length in meters parsed from osm values like `12`, `12 m` or `40'`, always finite and not negative
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
+pub struct Length(pub f64);
+
+impl TryFrom<&str> for Length {
+    type Error = ParseError;
+
+    fn try_from(input: &str) -> Result<Self, Self::Error> {
+        let (rest, value) = delimited(multispace0, length, multispace0)(input)
+            .map_err(|err| ParseError::from_nom(input, err))?;
+        if !rest.is_empty() {
+            return Err(ParseError::NotFullyConsumedError {
+                input: input.to_string(),
+                rest: rest.to_string(),
+            });
+        }
+        Ok(value)
+    }
+}
+
+/// Finite, non-negative number, rejecting the `nan` and `inf` forms accepted by `double`
+fn number(input: &str) -> IResult<&str, f64> {
+    verify(double, |number: &f64| number.is_finite() && *number >= 0.0)(input)
+}
+
+fn length(input: &str) -> IResult<&str, Length> {
+    alt((feet_inches, metric))(input)
+}
+
+/// Imperial notation, e.g. `40'` or `12'6"`
+fn feet_inches(input: &str) -> IResult<&str, Length> {
+    let (rest, (feet, inches)) = pair(
+        terminated(number, preceded(multispace0, char('\''))),
+        opt(delimited(multispace0, number, char('"'))),
+    )(input)?;
+    Ok((
+        rest,
+        Length(feet * 0.3048 + inches.unwrap_or_default() * 0.0254),
+    ))
+}
+
+/// Number with an optional unit, meters if none is given
+fn metric(input: &str) -> IResult<&str, Length> {
+    let (rest, (number, factor)) = pair(
+        number,
+        map(opt(preceded(multispace0, unit)), |factor| {
+            factor.unwrap_or(1.0)
+        }),
+    )(input)?;
+    Ok((rest, Length(number * factor)))
+}
+
+fn unit(input: &str) -> IResult<&str, f64> {
+    alt((
+        value(1000.0, tag("km")),
+        value(0.01, tag("cm")),
+        value(1.0, tag("m")),
+        value(0.3048, tag("ft")),
+        value(0.0254, tag("in")),
+    ))(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lengths_in_meters() {
+        let cases = [
+            ("12", 12.0),
+            ("12.5", 12.5),
+            (" 7 ", 7.0),
+            ("12 m", 12.0),
+            ("12m", 12.0),
+            ("1.2 km", 1200.0),
+            ("350 cm", 3.5),
+            ("10 ft", 3.048),
+            ("40 in", 1.016),
+            ("40'", 12.192),
+            ("12'6\"", 3.81),
+            ("12' 6\"", 3.81),
+            ("0", 0.0),
+        ];
+        for (input, meters) in cases {
+            let Length(length) = Length::try_from(input).unwrap();
+            assert!((length - meters).abs() < 1e-9, "{input}: {length}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_lengths() {
+        for input in [
+            "", "12,5", "12 mm", "m", "twelve", "12'6", "-3", "-3 m", "nan", "inf", "-inf",
+            "infinity", "inf m", "1e400",
+        ] {
+            assert!(Length::try_from(input).is_err(), "{input}");
+        }
+    }
+}
@@ -1 +1,2 @@
 pub mod housenumber;
+pub mod length;
@@ -1,130 +1,564 @@
 use geo::Centroid;
 use geo::Contains;
+use geo::Coord;
+use geo::GeodesicArea;
+use geo::Intersects;
+use geo::MultiPolygon;
 use geo::Point;
 use geo::Polygon;
-use osmpbfreader::Node;
-use osmpbfreader::NodeId;
 use osmpbfreader::OsmId;
 use osmpbfreader::OsmObj;
 use osmpbfreader::Tags;
-use osmpbfreader::Way;
-use rand::prelude::Distribution;
 use rand::Rng;
+use rayon::prelude::*;
 use serde::Deserialize;
 use serde::Serialize;
-use statrs::distribution::Categorical;
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::fmt::Display;
 
+use crate::classification::ClassificationRule;
+use crate::classification::FlatFormula;
 use crate::config::Config;
+use crate::config::DistributionMode;
+use crate::config::FlatEstimator;
+use crate::coordinates::node_point;
+use crate::coordinates::referenced_coord;
+use crate::estimate::FlatEstimate;
+use crate::estimate::FlatRule;
+use crate::household::HouseholdSampler;
+use crate::index::SpatialIndex;
+use crate::multipolygon::assemble_multipolygon;
+use crate::multipolygon::is_multipolygon;
 use crate::parser::housenumber::HouseNumberList;
+use crate::parser::housenumber::RangeMode;
+use crate::parser::length::Length;
+use crate::report::DataQualityReport;
+use crate::Error;
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum GenericGeometry {
     GenericPolygon(Polygon),
+    GenericMultiPolygon(MultiPolygon),
     GenericPoint(Point),
 }
 
+impl GenericGeometry {
+    /// Checks if a point lies inside the geometry, respecting holes
+    pub(crate) fn contains_point(&self, point: &Point) -> bool {
+        match self {
+            GenericGeometry::GenericPolygon(polygon) => polygon.contains(point),
+            GenericGeometry::GenericMultiPolygon(multi_polygon) => multi_polygon.contains(point),
+            GenericGeometry::GenericPoint(point2) => point2 == point,
+        }
+    }
+
+    /// Checks if a point lies inside or on the boundary of the geometry
+    pub(crate) fn intersects_point(&self, point: &Point) -> bool {
+        match self {
+            GenericGeometry::GenericPolygon(polygon) => polygon.intersects(point),
+            GenericGeometry::GenericMultiPolygon(multi_polygon) => multi_polygon.intersects(point),
+            GenericGeometry::GenericPoint(point2) => point2 == point,
+        }
+    }
+
+    /// Gets the centroid of the geometry, `None` for empty geometries
+    pub(crate) fn centroid(&self) -> Option<Point> {
+        match self {
+            GenericGeometry::GenericPolygon(polygon) => polygon.centroid(),
+            GenericGeometry::GenericMultiPolygon(multi_polygon) => multi_polygon.centroid(),
+            GenericGeometry::GenericPoint(point) => Some(*point),
+        }
+    }
+
+    /// Gets the geodesic area in m², points have no area
+    pub(crate) fn area(&self) -> f64 {
+        match self {
+            GenericGeometry::GenericPolygon(polygon) => polygon.geodesic_area_unsigned(),
+            GenericGeometry::GenericMultiPolygon(multi_polygon) => {
+                multi_polygon.geodesic_area_unsigned()
+            }
+            GenericGeometry::GenericPoint(_) => 0.0,
+        }
+    }
+
+    /// Checks if another geometry lies completely inside the geometry, respecting holes
+    pub(crate) fn contains(&self, geometry: &GenericGeometry) -> bool {
+        match (self, geometry) {
+            (_, GenericGeometry::GenericPoint(point)) => self.contains_point(point),
+            (
+                GenericGeometry::GenericPolygon(polygon),
+                GenericGeometry::GenericPolygon(polygon2),
+            ) => polygon.contains(polygon2),
+            (
+                GenericGeometry::GenericPolygon(polygon),
+                GenericGeometry::GenericMultiPolygon(multi_polygon),
+            ) => polygon.contains(multi_polygon),
+            (
+                GenericGeometry::GenericMultiPolygon(multi_polygon),
+                GenericGeometry::GenericPolygon(polygon),
+            ) => multi_polygon.contains(polygon),
+            (
+                GenericGeometry::GenericMultiPolygon(multi_polygon),
+                GenericGeometry::GenericMultiPolygon(multi_polygon2),
+            ) => multi_polygon.contains(multi_polygon2),
+            (GenericGeometry::GenericPoint(_), _) => false,
+        }
+    }
+}
+
+/// An osm node, way or multipolygon relation with its assembled geometry
 pub struct GenericWay {
-    pub polygon: Polygon,
+    pub id: OsmId,
+    pub geometry: GenericGeometry,
     pub tags: Tags,
 }
 
 impl GenericWay {
     /// Gets the number of house numbers in the area
-    fn calculate_house_number_count(&self, house_number_points: &[HouseNumberPoint]) -> usize {
+    fn calculate_house_number_count(
+        &self,
+        index: &SpatialIndex,
+        config: &Config,
+        report: &mut DataQualityReport,
+    ) -> Result<usize, Error> {
         // Count house numbers of way (tags)
-        let mut house_numbers = self
-            .tags
-            .get("addr:housenumber")
-            .and_then(|housenumber| HouseNumberList::try_from(housenumber.as_str()).ok())
-            .unwrap_or_default();
+        let mut house_numbers = match self.tags.get("addr:housenumber") {
+            Some(housenumber) => parse_housenumber_list(
+                self.id,
+                "addr:housenumber",
+                housenumber,
+                config.housenumber_range_mode,
+                config,
+                report,
+            )?,
+            None => HouseNumberList::default(),
+        };
+
+        // Point buildings only carry their own house numbers
+        if let GenericGeometry::GenericPoint(_) = self.geometry {
+            return Ok(house_numbers.count());
+        }
 
         // Count house numbers beeing positioned inside area (of way)
-        house_number_points
-            .iter()
-            .filter(|house_number| self.polygon.contains(&house_number.point))
+        index
+            .house_numbers_in(&self.geometry)
             .for_each(|house_number| {
-                house_numbers.merge(
-                    HouseNumberList::try_from(house_number.text.as_str()).unwrap_or_default(),
-                );
+                house_numbers.merge(house_number.numbers.clone());
             });
 
-        house_numbers.count()
+        Ok(house_numbers.count())
     }
 
-    /// Calculate number of flats inside building by tags
-    fn calculate_flat_count(&self, house_numbers: usize, config: &Config) -> usize {
+    /// Gets the flat count mapped explicitly by `building:flats`, `addr:flats` on the building or on nodes inside or
+    /// on its outline (e.g. entrances), or distinct `addr:unit` nodes, in this order
+    fn calculate_mapped_flats(
+        &self,
+        house_numbers: usize,
+        index: &SpatialIndex,
+        config: &Config,
+        report: &mut DataQualityReport,
+    ) -> Result<Option<FlatEstimate>, Error> {
         // If flat count is defined in tags, this is applied
-        if self.tags.contains_key("building:flats") {
-            let flat_count = self.tags["building:flats"].parse::<usize>().unwrap();
-            return flat_count;
+        if let Some(flats) = self.tags.get("building:flats") {
+            match flats.parse::<usize>() {
+                Ok(flat_count) => {
+                    return Ok(Some(FlatEstimate::new(
+                        FlatRule::ExplicitFlats,
+                        house_numbers,
+                        flat_count,
+                    )))
+                }
+                Err(_) => report.record(self.id, "building:flats", flats, "estimate", config)?,
+            }
         }
 
-        // If its a single home house, return 1
-        if config
-            .single_home_list
-            .contains(&self.tags["building"].to_string())
-        {
-            return 1;
+        let mut flats = match self.tags.get("addr:flats") {
+            // Flat numbers are not split by street sides
+            Some(flats) => parse_housenumber_list(
+                self.id,
+                "addr:flats",
+                flats,
+                RangeMode::All,
+                config,
+                report,
+            )?,
+            None => HouseNumberList::default(),
+        };
+        let mut units = BTreeSet::new();
+        for node in index.flat_addresses_on(&self.geometry) {
+            if let Some(node_flats) = node.tags.get("addr:flats") {
+                flats.merge(parse_housenumber_list(
+                    node.id,
+                    "addr:flats",
+                    node_flats,
+                    RangeMode::All,
+                    config,
+                    report,
+                )?);
+            }
+            if let Some(unit) = node.tags.get("addr:unit") {
+                let housenumber = node.tags.get("addr:housenumber").cloned();
+                units.insert((housenumber, unit.clone()));
+            }
         }
 
-        // Otherwise estimate flat count by building type
-        let mut flat_count: usize = 0;
-        if config
-            .apartment_list
-            .contains(&self.tags["building"].to_string())
-            || config
-                .unspecified_list
-                .contains(&self.tags["building"].to_string())
-        {
-            if house_numbers >= 1 {
-                flat_count = house_numbers * config.housenumber_factor;
-            } else {
-                flat_count = 4;
+        if flats.count() > 0 {
+            Ok(Some(FlatEstimate::new(
+                FlatRule::AddrFlats,
+                house_numbers,
+                flats.count(),
+            )))
+        } else if !units.is_empty() {
+            Ok(Some(FlatEstimate::new(
+                FlatRule::AddrUnits,
+                house_numbers,
+                units.len(),
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Calculate number of flats inside building by tags, explaining the rules applied
+    fn calculate_flat_estimate(
+        &self,
+        house_numbers: usize,
+        rule: Option<&ClassificationRule>,
+        parts: &[&GenericWay],
+        mixed_use: Option<String>,
+        config: &Config,
+        report: &mut DataQualityReport,
+    ) -> Result<FlatEstimate, Error> {
+        let mut estimate = FlatEstimate::new(FlatRule::None, house_numbers, 0);
+        estimate.classification = rule.map(|rule| rule.name.clone());
+        // Buildings matching no rule (garages, industrial, ...) are never estimated by area
+        let class = rule.map(|rule| rule.class);
+        let area = self.geometry.area();
+        estimate.parts = parts.len();
+        let part_storeys = self.calculate_part_storeys(parts, config, report)?;
+        let levels_known = part_storeys.is_some()
+            || self.tags.contains_key("building:levels")
+            || self.tags.contains_key("height");
+        estimate.mixed_use = mixed_use;
+        let area_per_flat = match config.flat_estimator {
+            FlatEstimator::Tags => None,
+            FlatEstimator::Area => class.and_then(|class| config.area_per_flat.get(&class)),
+            FlatEstimator::FloorArea => {
+                class.and_then(|class| config.floor_area_per_flat.get(&class))
+            }
+        }
+        .filter(|area_per_flat| area > 0.0 && **area_per_flat > 0.0);
+        match (config.flat_estimator, area_per_flat) {
+            // Storeys are part of the floor area, so levels are not multiplied in again
+            (FlatEstimator::FloorArea, Some(floor_area_per_flat)) => {
+                let mut storeys = match part_storeys {
+                    Some(storeys) => storeys,
+                    None => self.calculate_storeys(config, report)?,
+                };
+                if estimate.mixed_use.is_some() && levels_known {
+                    storeys = (storeys - config.mixed_use_levels).max(0.0);
+                    estimate.mixed_use_levels = Some(config.mixed_use_levels);
+                }
+                estimate.rule = FlatRule::FloorArea;
+                estimate.area = Some(area);
+                estimate.storeys = Some(storeys);
+                estimate.area_per_flat = Some(*floor_area_per_flat);
+                estimate.flats = (area * storeys / floor_area_per_flat).round() as usize;
+                if estimate.mixed_use.is_some() && !levels_known {
+                    apply_mixed_use_share(&mut estimate, config);
+                }
+                return Ok(estimate);
+            }
+            // Estimate flats per storey by footprint area if configured for the building class
+            (_, Some(area_per_flat)) => {
+                estimate.rule = FlatRule::FootprintArea;
+                estimate.area = Some(area);
+                estimate.area_per_flat = Some(*area_per_flat);
+                estimate.flats = (area / area_per_flat).round() as usize;
+            }
+            (_, None) => {
+                self.apply_tag_rules(&mut estimate, rule, config);
+                // Rules without levels (e.g. single homes) are not multiplied by levels
+                if rule.is_some_and(|rule| !rule.levels) {
+                    return Ok(estimate);
+                }
+            }
+        }
+
+        // Increase flat count by building levels if specified, falling back to the storeys of the building parts
+        let levels = match self.tags.get("building:levels") {
+            Some(levels) => Some(
+                match levels.parse::<f32>() {
+                    Ok(levels) => levels,
+                    Err(_) => {
+                        report.record(self.id, "building:levels", levels, "0", config)?;
+                        0.0
+                    }
+                }
+                .floor() as usize,
+            ),
+            None => part_storeys.map(|storeys| storeys.round() as usize),
+        };
+        match (levels, &estimate.mixed_use) {
+            // Non-residential storeys of mixed use buildings do not count
+            (Some(levels), Some(_)) => {
+                let levels = (levels as f64 - config.mixed_use_levels).max(0.0).floor() as usize;
+                estimate.mixed_use_levels = Some(config.mixed_use_levels);
+                estimate.levels = Some(levels);
+                estimate.level_factor = Some(config.level_factor);
+                estimate.flats = estimate.flats * levels * config.level_factor;
+            }
+            (Some(levels), None) => {
+                estimate.levels = Some(levels);
+                estimate.level_factor = Some(config.level_factor);
+                estimate.flats = estimate.flats * levels * config.level_factor;
+            }
+            (None, Some(_)) => apply_mixed_use_share(&mut estimate, config),
+            (None, None) => {}
+        }
+
+        Ok(estimate)
+    }
+
+    /// Gets the habitable storeys from `building:levels` or `height`, counting `roof:levels` by the roof level share
+    fn calculate_storeys(
+        &self,
+        config: &Config,
+        report: &mut DataQualityReport,
+    ) -> Result<f64, Error> {
+        let levels = match self.parse_number("building:levels", "height", config, report)? {
+            Some(levels) => {
+                let min_level = self
+                    .parse_number("building:min_level", "0", config, report)?
+                    .unwrap_or_default();
+                Some(levels - min_level)
+            }
+            None => match self.parse_length("height", "1", config, report)? {
+                Some(height) => {
+                    let min_height = self
+                        .parse_length("min_height", "0", config, report)?
+                        .unwrap_or_default();
+                    Some(((height - min_height) / config.level_height).round())
+                }
+                None => None,
+            },
+        };
+        let roof_levels = self
+            .parse_number("roof:levels", "0", config, report)?
+            .unwrap_or_default();
+
+        // Buildings without any height information are assumed to have a single storey
+        Ok(levels.unwrap_or(1.0).max(0.0) + roof_levels.max(0.0) * config.roof_level_share)
+    }
+
+    /// Gets the storeys of the building aggregated from its parts, weighted by their footprint area.
+    /// Parts covering only some of the outline stand for the whole building.
+    fn calculate_part_storeys(
+        &self,
+        parts: &[&GenericWay],
+        config: &Config,
+        report: &mut DataQualityReport,
+    ) -> Result<Option<f64>, Error> {
+        let mut part_area = 0.0;
+        let mut floor_area = 0.0;
+        for part in parts {
+            let area = part.geometry.area();
+            part_area += area;
+            floor_area += area * part.calculate_storeys(config, report)?;
+        }
+        if part_area <= 0.0 {
+            return Ok(None);
+        }
+        Ok(Some(floor_area / part_area))
+    }
+
+    /// Parses a numeric tag, reporting malformed values
+    fn parse_number(
+        &self,
+        key: &str,
+        fallback: &str,
+        config: &Config,
+        report: &mut DataQualityReport,
+    ) -> Result<Option<f64>, Error> {
+        let Some(value) = self.tags.get(key) else {
+            return Ok(None);
+        };
+        match value.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(Some(number)),
+            _ => {
+                report.record(self.id, key, value, fallback, config)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Parses a length tag in meters (e.g. `12`, `12 m` or `40'`), reporting malformed values
+    fn parse_length(
+        &self,
+        key: &str,
+        fallback: &str,
+        config: &Config,
+        report: &mut DataQualityReport,
+    ) -> Result<Option<f64>, Error> {
+        let Some(value) = self.tags.get(key) else {
+            return Ok(None);
+        };
+        match Length::try_from(value.as_str()) {
+            Ok(Length(length)) => Ok(Some(length)),
+            _ => {
+                report.record(self.id, key, value, fallback, config)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Estimates the flat count by the flat formula of the classification rule
+    fn apply_tag_rules(
+        &self,
+        estimate: &mut FlatEstimate,
+        rule: Option<&ClassificationRule>,
+        config: &Config,
+    ) {
+        let Some(rule) = rule else {
+            return;
+        };
+        match &rule.flats {
+            FlatFormula::Fixed(flats) => {
+                estimate.rule = FlatRule::Fixed;
+                estimate.flats = *flats;
+            }
+            FlatFormula::HouseNumbers { factor, default } => {
+                if estimate.house_numbers >= 1 {
+                    let factor = factor.unwrap_or(config.housenumber_factor);
+                    estimate.rule = FlatRule::HouseNumberFactor;
+                    estimate.housenumber_factor = Some(factor);
+                    estimate.flats = estimate.house_numbers * factor;
+                } else {
+                    estimate.rule = FlatRule::DefaultFlats;
+                    estimate.flats = *default;
+                }
             }
-        } else if self.tags["building"] == "yes" && house_numbers >= 1 {
-            flat_count = house_numbers;
         }
+    }
 
-        // Increase flat count by building levels if specified
-        if self.tags.contains_key("building:levels") {
-            let levels = self.tags["building:levels"]
-                .parse::<f32>()
-                .unwrap_or(0.0)
-                .floor() as usize;
-            flat_count = flat_count * levels * config.level_factor;
+    /// Finds the first classification rule matching the building. Buildings of class other with a
+    /// residential `building:use` are classified by their use. The returned bool flags the mixed use of
+    /// non-residential buildings, a generic `building=yes` just takes the class of its use.
+    fn classification_rule<'a>(
+        &self,
+        config: &'a Config,
+    ) -> (Option<&'a ClassificationRule>, bool) {
+        let rule = config.classification_rule(&self.tags);
+        if rule.is_some_and(|rule| rule.class != BuildingClass::Other) {
+            return (rule, false);
+        }
+        let Some(usage) = self.tags.get("building:use") else {
+            return (rule, false);
+        };
+        let mut tags = self.tags.clone();
+        tags.insert("building".into(), usage.clone());
+        match config
+            .classification_rule(&tags)
+            .filter(|rule| rule.class != BuildingClass::Other)
+        {
+            Some(use_rule) => (
+                Some(use_rule),
+                self.tags
+                    .get("building")
+                    .is_none_or(|building| building != "yes"),
+            ),
+            None => (rule, false),
         }
+    }
+
+    /// Gets the reason a building is considered mixed use: non-residential tags on the building,
+    /// a residential `building:use` of a non-residential building or a non-residential poi inside it
+    fn mixed_use(
+        &self,
+        classified_by_use: bool,
+        index: &SpatialIndex,
+        config: &Config,
+    ) -> Option<String> {
+        if let Some(tag) = mixed_use_tag(&self.tags, config) {
+            return Some(tag);
+        }
+        if classified_by_use {
+            return Some(format!(
+                "building={}, building:use={}",
+                self.tags["building"], self.tags["building:use"]
+            ));
+        }
+        index.mixed_use_pois_in(&self.geometry).find_map(|poi| {
+            mixed_use_tag(&poi.tags, config)
+                .map(|tag| format!("{tag} on {}/{}", osm_type(poi.id), poi.id.inner_id()))
+        })
+    }
 
-        flat_count
+    /// Gets the source tags whitelisted for the output. A trailing `*` matches any key with that prefix.
+    fn output_tags(&self, config: &Config) -> BTreeMap<String, String> {
+        self.tags
+            .iter()
+            .filter(|(key, _)| {
+                config
+                    .output_tags
+                    .iter()
+                    .any(|output_tag| match output_tag.strip_suffix('*') {
+                        Some(prefix) => key.starts_with(prefix),
+                        None => key.as_str() == output_tag,
+                    })
+            })
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
     }
 
     /// Estimates number of flats inside building
-    pub fn calculate_building_metrics(
+    pub(crate) fn calculate_building_metrics(
         &self,
-        house_number_points: &[HouseNumberPoint],
+        index: &SpatialIndex,
         config: &Config,
-    ) -> Building {
-        let house_number_count = self.calculate_house_number_count(house_number_points);
-        let flat_count = self.calculate_flat_count(house_number_count, config);
-        Building {
-            geometry: GenericGeometry::GenericPolygon(self.polygon.clone()),
-            flats: flat_count,
+        report: &mut DataQualityReport,
+    ) -> Result<Building, Error> {
+        let house_number_count = self.calculate_house_number_count(index, config, report)?;
+        let (rule, classified_by_use) = self.classification_rule(config);
+        let class = rule.map_or(BuildingClass::Other, |rule| rule.class);
+        let parts: Vec<&GenericWay> = index.parts_of(self).collect();
+        let mixed_use = self.mixed_use(classified_by_use, index, config);
+        let estimate =
+            match self.calculate_mapped_flats(house_number_count, index, config, report)? {
+                Some(estimate) => estimate,
+                None => self.calculate_flat_estimate(
+                    house_number_count,
+                    rule,
+                    &parts,
+                    mixed_use,
+                    config,
+                    report,
+                )?,
+            };
+        Ok(Building {
+            id: self.id,
+            geometry: self.geometry.clone(),
+            tags: self.output_tags(config),
+            class,
+            flats: estimate.flats,
+            estimate,
             pop: 0,
-        }
+        })
     }
 
-    fn contains(&self, geometry: &GenericGeometry) -> bool {
-        match geometry {
-            GenericGeometry::GenericPolygon(polygon) => self.polygon.contains(polygon),
-            GenericGeometry::GenericPoint(point2) => self.polygon.contains(point2),
+    /// Checks if a building geometry is excluded by this area. Point areas exclude the building they are placed in.
+    pub(crate) fn excludes(&self, geometry: &GenericGeometry) -> bool {
+        match &self.geometry {
+            GenericGeometry::GenericPoint(point) => geometry.contains_point(point),
+            _ => self.geometry.contains(geometry),
         }
     }
 }
 
 pub struct HouseNumberPoint {
-    point: Point,
-    text: String,
+    pub(crate) point: Point,
+    pub(crate) numbers: HouseNumberList,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -146,20 +580,56 @@ impl FromIterator<Building> for Buildings {
     }
 }
 
-impl From<(Vec<GenericWay>, &Vec<HouseNumberPoint>, &Config)> for Buildings {
-    fn from(item: (Vec<GenericWay>, &Vec<HouseNumberPoint>, &Config)) -> Self {
-        Buildings(
-            item.0
-                .into_iter()
-                .map(|way| Building::from((way, item.1, item.2)))
-                .collect(),
-        )
+impl Buildings {
+    /// Calculates the building metrics of all ways, collecting data quality issues on the way
+    pub(crate) fn from_ways(
+        ways: Vec<GenericWay>,
+        index: &SpatialIndex,
+        config: &Config,
+    ) -> Result<(Buildings, DataQualityReport), Error> {
+        let results = ways
+            .into_par_iter()
+            .map(|way| {
+                let mut report = DataQualityReport::default();
+                let building = way.calculate_building_metrics(index, config, &mut report)?;
+                Ok((building, report))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut buildings = Vec::with_capacity(results.len());
+        let mut report = DataQualityReport::default();
+        for (building, building_report) in results {
+            buildings.push(building);
+            report.merge(building_report);
+        }
+        Ok((Buildings(buildings), report))
     }
-}
 
-impl Buildings {
-    /// Distributes a known population to buildings
-    pub fn distribute_population(&mut self, inhabitants_total: u64, config: &Config) {
+    /// Distributes a known population to buildings using the configured distribution mode
+    pub fn distribute_population<R: Rng + ?Sized>(
+        &mut self,
+        inhabitants_total: u64,
+        config: &Config,
+        rng: &mut R,
+    ) -> Result<(), Error> {
+        if self.0.iter().all(|building| building.flats == 0) {
+            return Err(Error::NoFlats);
+        }
+
+        match config.distribution_mode {
+            DistributionMode::Random => self.distribute_randomly(inhabitants_total, config, rng),
+            DistributionMode::Proportional => self.distribute_proportionally(inhabitants_total),
+        }
+        Ok(())
+    }
+
+    /// Distributes the population person by person to random flats
+    fn distribute_randomly<R: Rng + ?Sized>(
+        &mut self,
+        inhabitants_total: u64,
+        config: &Config,
+        rng: &mut R,
+    ) {
         // Gather total flat count
         let total_flat_count: usize = self.0.iter().map(|building| building.flats).sum();
 
@@ -167,10 +637,11 @@ impl Buildings {
         let mut flat_inhabitants: Vec<u64> = vec![0; total_flat_count];
         let mut inhabitants_to_distribute = inhabitants_total;
         while inhabitants_to_distribute > 0 {
-            let flat_index = rand::thread_rng().gen_range(0..total_flat_count - 1);
+            let flat_index = rng.gen_range(0..total_flat_count);
             if flat_inhabitants[flat_index] > config.reroll_threshold
-                && rand::thread_rng().gen_range(0..config.reroll_probability)
-                    > config.reroll_threshold.try_into().unwrap()
+                && config.reroll_probability > 0
+                && u64::try_from(rng.gen_range(0..config.reroll_probability))
+                    .is_ok_and(|reroll| reroll > config.reroll_threshold)
             {
                 continue;
             }
@@ -181,7 +652,6 @@ impl Buildings {
         // Add population tag to buildings
         let mut flat_offset = 0;
         self.0.iter_mut().for_each(|building| {
-            let building = building;
             let flat_count = building.flats;
             let mut population: u64 = 0;
             for flat_inhabitant_count in flat_inhabitants.iter().skip(flat_offset).take(flat_count)
@@ -193,18 +663,50 @@ impl Buildings {
         });
     }
 
-    /// Estimates the population of buildings by applying german household sizes by occurrence probability
-    pub fn estimate_population(&mut self) {
-        let dist = Categorical::new(&[0.20737853, 0.33310260, 0.17661846, 0.18911436, 0.09378605])
-            .unwrap();
+    /// Distributes the population proportionally to the flat count using largest remainder allocation
+    fn distribute_proportionally(&mut self, inhabitants_total: u64) {
+        let total_flat_count: u128 = self.0.iter().map(|building| building.flats as u128).sum();
+
+        // Assign the integer part of each quota and keep the remainders
+        let mut remainders: Vec<(u128, usize)> = Vec::with_capacity(self.0.len());
+        let mut inhabitants_to_distribute = inhabitants_total;
+        for (index, building) in self.0.iter_mut().enumerate() {
+            let share = inhabitants_total as u128 * building.flats as u128;
+            let quota = (share / total_flat_count) as u64;
+            building.pop += quota;
+            inhabitants_to_distribute -= quota;
+            remainders.push((share % total_flat_count, index));
+        }
+
+        // Hand out the rest to the buildings with the largest remainders
+        let rest = inhabitants_to_distribute as usize;
+        if rest > 0 {
+            remainders.select_nth_unstable_by(rest - 1, |a, b| b.cmp(a));
+            for (_, index) in &remainders[..rest] {
+                self.0[*index].pop += 1;
+            }
+        }
+    }
+
+    /// Estimates the population of buildings by applying the configured household sizes of their class by occurrence probability
+    pub fn estimate_population<R: Rng + ?Sized>(
+        &mut self,
+        config: &Config,
+        rng: &mut R,
+    ) -> Result<(), Error> {
+        let mut samplers = BTreeMap::new();
+        for class in BuildingClass::ALL {
+            samplers.insert(
+                class,
+                HouseholdSampler::new(&config.household_distribution(class)?)?,
+            );
+        }
+
         self.0.iter_mut().for_each(|building| {
-            building.pop = dist
-                .clone()
-                .sample_iter(&mut rand::thread_rng())
-                .take(building.flats)
-                .map(|p| (p as u64) + 1)
-                .sum();
-        })
+            let sampler = &samplers[&building.class];
+            building.pop = (0..building.flats).map(|_| sampler.sample(rng)).sum();
+        });
+        Ok(())
     }
 
     pub fn into_inner(self) -> Vec<Building> {
@@ -215,57 +717,112 @@ impl Buildings {
         self.0.iter()
     }
 
-    pub(crate) fn exclude_in(mut self, area: &[GenericWay]) -> Self {
-        self.0
-            .retain(|building| !area.iter().any(|area| area.contains(&building.geometry)));
-        self
+    /// Finds a building by its osm id
+    pub fn get(&self, id: OsmId) -> Option<&Building> {
+        self.0.iter().find(|building| building.id == id)
+    }
+
+    pub(crate) fn exclude_in(self, index: &SpatialIndex) -> Self {
+        Buildings(
+            self.0
+                .into_par_iter()
+                .filter(|building| !index.is_excluded(&building.geometry))
+                .collect(),
+        )
     }
 
     pub(crate) fn centroid(&mut self) {
-        self.0.iter_mut().for_each(|building| {
+        self.0.par_iter_mut().for_each(|building| {
             building.centroid();
         });
     }
 }
 
+/// Class of a building, deciding about the household sizes applied to its flats
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildingClass {
+    SingleHome,
+    Apartment,
+    Unspecified,
+    Other,
+}
+
+impl BuildingClass {
+    pub const ALL: [BuildingClass; 4] = [
+        BuildingClass::SingleHome,
+        BuildingClass::Apartment,
+        BuildingClass::Unspecified,
+        BuildingClass::Other,
+    ];
+}
+
 /// A building is a area or point with information about estimated flats and population
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Building {
+    pub id: OsmId,
     pub geometry: GenericGeometry,
+    pub tags: BTreeMap<String, String>,
+    pub class: BuildingClass,
     pub flats: usize,
+    pub estimate: FlatEstimate,
     pub pop: u64,
 }
 
-impl From<(GenericWay, &Vec<HouseNumberPoint>, &Config)> for Building {
-    fn from(item: (GenericWay, &Vec<HouseNumberPoint>, &Config)) -> Self {
-        item.0.calculate_building_metrics(item.1, item.2)
-    }
-}
-
 impl Display for Building {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Building {{ polygon: {:?}, flats: {:?}, population: {:?} }}",
-            self.geometry, self.flats, self.pop
+            "Building {{ id: {:?}, polygon: {:?}, flats: {:?}, population: {:?} }}",
+            self.id, self.geometry, self.flats, self.pop
         )
     }
 }
 
 impl Building {
+    /// Replaces the geometry by its centroid. Empty geometries are kept as they are.
     pub fn centroid(&mut self) {
-        match &self.geometry {
-            GenericGeometry::GenericPolygon(polygon) => {
-                self.geometry = GenericGeometry::GenericPoint(polygon.centroid().unwrap());
-            }
-            GenericGeometry::GenericPoint(_) => {}
+        if let Some(centroid) = self.geometry.centroid() {
+            self.geometry = GenericGeometry::GenericPoint(centroid);
         }
     }
 }
 
+/// Gets the osm type name of an id as used on openstreetmap.org
+pub(crate) fn osm_type(object: OsmId) -> &'static str {
+    match object {
+        OsmId::Node(_) => "node",
+        OsmId::Way(_) => "way",
+        OsmId::Relation(_) => "relation",
+    }
+}
+
 /// Check if osm obj is building
 pub(crate) fn is_building(obj: &osmpbfreader::OsmObj) -> bool {
-    (obj.is_node() || obj.is_way()) && obj.tags().contains_key("building")
+    (obj.is_node() || obj.is_way() || is_multipolygon(obj))
+        && obj.tags().contains_key("building")
+        && !is_building_part(obj)
+}
+
+/// Check if osm obj is a node listing flats by `addr:flats` or `addr:unit`, e.g. an entrance
+pub(crate) fn is_flat_address_node(obj: &osmpbfreader::OsmObj) -> bool {
+    obj.is_node()
+        && !is_building(obj)
+        && (obj.tags().contains_key("addr:flats") || obj.tags().contains_key("addr:unit"))
+}
+
+/// Check if osm obj is a building part, which is aggregated into its building instead of counted separately
+pub(crate) fn is_building_part(obj: &osmpbfreader::OsmObj) -> bool {
+    (obj.is_way() || is_multipolygon(obj))
+        && obj
+            .tags()
+            .get("building:part")
+            .is_some_and(|part| part != "no")
+}
+
+/// Check if osm obj is a building relation grouping an outline with its parts
+pub(crate) fn is_building_relation(obj: &osmpbfreader::OsmObj) -> bool {
+    obj.is_relation() && obj.tags().contains("type", "building")
 }
 
 /// Check if osm obj is housenumber
@@ -273,84 +830,426 @@ pub(crate) fn is_housenumber_node(obj: &osmpbfreader::OsmObj) -> bool {
     obj.is_node() && obj.tags().contains_key("addr:housenumber")
 }
 
-/// Check if osm obj is part of the exclude areas
+/// Check if osm obj is part of the exclude areas. Mixed use pois reduce the flats of their building instead.
 pub(crate) fn is_exclude_area(obj: &osmpbfreader::OsmObj, config: &Config) -> bool {
-    obj.is_way()
-        && ((obj.tags().contains_key("landuse")
-            && config
-                .exclude_landuse
-                .contains(&obj.tags()["landuse"].to_string()))
-            || config
-                .exclude_tags
-                .iter()
-                .any(|exclude_tag| obj.tags().contains_key(exclude_tag.as_str())))
+    let excluded_landuse = obj
+        .tags()
+        .get("landuse")
+        .is_some_and(|landuse| config.exclude_landuse.contains(&landuse.to_string()));
+    let excluded_tag = config
+        .exclude_tags
+        .iter()
+        .any(|exclude_tag| obj.tags().contains_key(exclude_tag.as_str()));
+    // Only landuse nodes exclude the building they are placed in, other tagged nodes are just pois
+    match obj {
+        OsmObj::Node(_) => excluded_landuse && !is_mixed_use_poi(obj, config),
+        // Mixed use buildings reduce their flats instead of excluding themselves
+        _ => {
+            (obj.is_way() || is_multipolygon(obj))
+                && (excluded_landuse || excluded_tag)
+                && !(is_building(obj) && mixed_use_tag(obj.tags(), config).is_some())
+        }
+    }
 }
 
-pub(crate) fn load_ways(osm_buildings: BTreeMap<OsmId, OsmObj>) -> Vec<GenericWay> {
-    // Extract buildings and all nodes
-    let osm_building_ways: Vec<Way> = osm_buildings
-        .clone()
-        .into_iter()
-        .filter_map(|(_, obj)| match obj {
-            OsmObj::Way(inner) => Some(inner),
-            _ => None,
-        })
-        .collect();
+/// Check if osm obj is a non-residential poi node, turning the building it is placed in into a mixed use building
+pub(crate) fn is_mixed_use_poi(obj: &osmpbfreader::OsmObj, config: &Config) -> bool {
+    obj.is_node() && !is_building(obj) && mixed_use_tag(obj.tags(), config).is_some()
+}
 
-    let osm_building_nodes: BTreeMap<NodeId, Node> = osm_buildings
-        .into_iter()
-        .filter_map(|(_, obj)| match obj {
-            OsmObj::Node(inner) => Some(inner),
-            _ => None,
-        })
-        .map(|node| (node.id, node))
-        .collect();
+/// Gets the first non-residential tag, e.g. `shop=bakery`
+fn mixed_use_tag(tags: &Tags, config: &Config) -> Option<String> {
+    config
+        .mixed_use_tags
+        .iter()
+        .find_map(|key| tags.get(key.as_str()).map(|value| format!("{key}={value}")))
+}
 
-    // Create geometry for buildings
-    osm_building_ways
-        .into_iter()
-        .map(|obj| {
-            let coords: Vec<(f64, f64)> = obj
+/// Removes the non-residential share from the flats of a mixed use building without known levels
+fn apply_mixed_use_share(estimate: &mut FlatEstimate, config: &Config) {
+    estimate.mixed_use_share = Some(config.mixed_use_share);
+    estimate.flats =
+        (estimate.flats as f64 * (1.0 - config.mixed_use_share).max(0.0)).round() as usize;
+}
+
+/// Maps the outline of every building relation to its parts
+pub(crate) fn load_part_relations(
+    osm_objs: &BTreeMap<OsmId, OsmObj>,
+) -> BTreeMap<OsmId, Vec<OsmId>> {
+    let mut part_relations = BTreeMap::new();
+    for relation in osm_objs
+        .values()
+        .filter(|obj| is_building_relation(obj))
+        .filter_map(|obj| obj.relation())
+    {
+        let Some(outline) = relation.refs.iter().find(|member| member.role == "outline") else {
+            continue;
+        };
+        let parts = relation
+            .refs
+            .iter()
+            .filter(|member| member.role == "part")
+            .map(|member| member.member)
+            .collect();
+        part_relations.insert(outline.member, parts);
+    }
+    part_relations
+}
+
+/// Creates geometries for all nodes, ways and multipolygon relations matching the filter
+pub(crate) fn load_ways<F>(
+    osm_objs: &BTreeMap<OsmId, OsmObj>,
+    filter: F,
+    config: &Config,
+    report: &mut DataQualityReport,
+) -> Result<Vec<GenericWay>, Error>
+where
+    F: Fn(&OsmObj) -> bool,
+{
+    let mut ways = vec![];
+    for obj in osm_objs.values().filter(|obj| filter(obj)) {
+        let geometry = match load_geometry(obj, osm_objs) {
+            Ok(Some(geometry)) => geometry,
+            Ok(None) => continue,
+            // Objects cut off at the border of an extract are skipped in lenient mode
+            Err(Error::MissingNode { object, node }) if config.lenient => {
+                report.record(object, "node", &node.0.to_string(), "skipped", config)?;
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        ways.push(GenericWay {
+            id: obj.id(),
+            geometry,
+            tags: obj.tags().clone(),
+        });
+    }
+    Ok(ways)
+}
+
+fn load_geometry(
+    obj: &OsmObj,
+    osm_objs: &BTreeMap<OsmId, OsmObj>,
+) -> Result<Option<GenericGeometry>, Error> {
+    match obj {
+        OsmObj::Way(way) => {
+            let coords: Vec<Coord> = way
                 .nodes
                 .iter()
-                .map(|node_id| {
-                    (
-                        osm_building_nodes[node_id].decimicro_lon as f64 / 10000000.,
-                        osm_building_nodes[node_id].decimicro_lat as f64 / 10000000.,
-                    )
-                })
-                .collect();
+                .map(|node_id| referenced_coord(obj.id(), *node_id, osm_objs))
+                .collect::<Result<_, _>>()?;
             let line_string = geo::LineString::from(coords);
             let polygon = Polygon::new(line_string, vec![]); // Make to confex hull to make centroid
-            GenericWay {
-                polygon,
-                tags: obj.tags,
-            }
-        })
-        .collect()
+            Ok(Some(GenericGeometry::GenericPolygon(polygon)))
+        }
+        OsmObj::Relation(relation) => Ok(
+            assemble_multipolygon(relation, osm_objs)?.map(GenericGeometry::GenericMultiPolygon)
+        ),
+        OsmObj::Node(node) => Ok(Some(GenericGeometry::GenericPoint(node_point(node)))),
+    }
 }
 
 pub(crate) fn load_housenumbers(
-    osm_housenumbers: BTreeMap<OsmId, OsmObj>,
-) -> Vec<HouseNumberPoint> {
-    let osm_housenumber_nodes: BTreeMap<NodeId, Node> = osm_housenumbers
-        .into_iter()
-        .filter_map(|(_, obj)| match obj {
-            OsmObj::Node(inner) => Some(inner),
-            _ => None,
-        })
-        .map(|node| (node.id, node))
-        .collect(); // TODO: How to get rid of this map function?
-
-    osm_housenumber_nodes
+    osm_housenumbers: &BTreeMap<OsmId, OsmObj>,
+    config: &Config,
+    report: &mut DataQualityReport,
+) -> Result<Vec<HouseNumberPoint>, Error> {
+    osm_housenumbers
         .values()
+        .filter(|obj| is_housenumber_node(obj))
+        .filter_map(|obj| obj.node())
         .map(|obj| {
-            let point = Point::new(
-                obj.decimicro_lat as f64 / 10000000.,
-                obj.decimicro_lon as f64 / 10000000.,
-            );
-            let text = obj.tags["addr:housenumber"].to_string();
-            HouseNumberPoint { point, text }
+            let point = node_point(obj);
+            let numbers = parse_housenumber_list(
+                OsmId::Node(obj.id),
+                "addr:housenumber",
+                &obj.tags["addr:housenumber"],
+                config.housenumber_range_mode,
+                config,
+                report,
+            )?;
+            Ok(HouseNumberPoint { point, numbers })
         })
         .collect()
 }
+
+/// Parses a house number list tag like `addr:housenumber` or `addr:flats`, reporting unparseable values
+fn parse_housenumber_list(
+    object: OsmId,
+    key: &str,
+    value: &str,
+    mode: RangeMode,
+    config: &Config,
+    report: &mut DataQualityReport,
+) -> Result<HouseNumberList, Error> {
+    match HouseNumberList::parse(value, mode, &config.housenumber_locales) {
+        Ok(numbers) => Ok(numbers),
+        Err(_) => {
+            report.record(object, key, value, "ignored", config)?;
+            Ok(HouseNumberList::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::household::HouseholdDistribution;
+    use osmpbfreader::WayId;
+
+    fn buildings(flats: &[usize]) -> Buildings {
+        flats
+            .iter()
+            .enumerate()
+            .map(|(index, flats)| Building {
+                id: OsmId::Way(WayId(index as i64)),
+                geometry: GenericGeometry::GenericPoint(Point::new(13.8, 47.9)),
+                tags: BTreeMap::new(),
+                class: BuildingClass::Apartment,
+                flats: *flats,
+                estimate: FlatEstimate::new(FlatRule::ExplicitFlats, 0, *flats),
+                pop: 0,
+            })
+            .collect()
+    }
+
+    /// A footprint of about 100 m × 90 m
+    fn way(building: &str) -> GenericWay {
+        rectangle(1, 13.7990, 13.8002, &[("building", building)])
+    }
+
+    /// A way spanning the latitudes of [`way`] between the given longitudes
+    fn rectangle(id: i64, min_lon: f64, max_lon: f64, tags: &[(&str, &str)]) -> GenericWay {
+        GenericWay {
+            id: OsmId::Way(WayId(id)),
+            geometry: GenericGeometry::GenericPolygon(Polygon::new(
+                geo::LineString::from(vec![
+                    (min_lon, 47.9180),
+                    (max_lon, 47.9180),
+                    (max_lon, 47.9189),
+                    (min_lon, 47.9189),
+                    (min_lon, 47.9180),
+                ]),
+                vec![],
+            )),
+            tags: tags
+                .iter()
+                .map(|(key, value)| ((*key).into(), (*value).into()))
+                .collect(),
+        }
+    }
+
+    fn estimate_flats(building: &str, flat_estimator: FlatEstimator) -> FlatEstimate {
+        let config = Config::builder().flat_estimator(flat_estimator).build();
+        let way = way(building);
+        let (rule, _) = way.classification_rule(&config);
+        way.calculate_flat_estimate(0, rule, &[], None, &config, &mut Default::default())
+            .unwrap()
+    }
+
+    fn distribute(flats: &[usize], inhabitants_total: u64) -> Vec<u64> {
+        let mut buildings = buildings(flats);
+        buildings.distribute_proportionally(inhabitants_total);
+        buildings.iter().map(|building| building.pop).collect()
+    }
+
+    #[test]
+    fn distributes_proportionally_to_flats() {
+        assert_eq!(distribute(&[1, 2, 3], 60), [10, 20, 30]);
+
+        let pop = distribute(&[3, 7, 0, 1, 9, 0, 13], 1001);
+        assert_eq!(pop.iter().sum::<u64>(), 1001);
+        assert_eq!((pop[2], pop[5]), (0, 0));
+        // Quotas of 1001 / 33 per flat are rounded to the nearest integers
+        for (pop, flats) in pop.iter().zip([3, 7, 0, 1, 9, 0, 13]) {
+            let quota = 1001. * flats as f64 / 33.;
+            assert!((*pop as f64 - quota).abs() < 1., "{pop} for {flats} flats");
+        }
+    }
+
+    fn estimate_population(config: &Config) -> Result<Vec<u64>, Error> {
+        let mut buildings = buildings(&[1, 4, 2, 8, 3]);
+        buildings.estimate_population(config, &mut config.rng())?;
+        Ok(buildings.iter().map(|building| building.pop).collect())
+    }
+
+    #[test]
+    fn samples_household_sizes_of_the_building_class() {
+        let config = Config::builder().seed(Some(7)).build();
+        let germany = estimate_population(&config).unwrap();
+        assert_eq!(germany, estimate_population(&config).unwrap());
+
+        let config = Config::builder()
+            .seed(Some(7))
+            .household_preset(
+                "large".to_string(),
+                HouseholdDistribution::new(vec![0.0, 0.0, 0.0, 1.0], 4.0),
+            )
+            .household_class_distribution(BuildingClass::Apartment, "large".to_string())
+            .build();
+        let large = estimate_population(&config).unwrap();
+        assert_eq!(large, [4, 16, 8, 32, 12]);
+        assert_ne!(large, germany);
+
+        // Overrides of other classes leave apartments at the default distribution
+        let config = Config::builder()
+            .seed(Some(7))
+            .household_preset(
+                "large".to_string(),
+                HouseholdDistribution::new(vec![0.0, 0.0, 0.0, 1.0], 4.0),
+            )
+            .household_class_distribution(BuildingClass::SingleHome, "large".to_string())
+            .build();
+        assert_eq!(estimate_population(&config).unwrap(), germany);
+    }
+
+    #[test]
+    fn fails_on_unknown_household_distributions() {
+        let config = Config::builder()
+            .household_class_distribution(BuildingClass::Other, "atlantis".to_string())
+            .build();
+        assert!(matches!(
+            estimate_population(&config),
+            Err(Error::UnknownHouseholdDistribution(name)) if name == "atlantis"
+        ));
+    }
+
+    fn building_metrics(tags: &[(&str, &str)]) -> Building {
+        let config = Config::builder().build();
+        let index = SpatialIndex::new(vec![], vec![], vec![], BTreeMap::new(), vec![], vec![]);
+        rectangle(1, 13.7990, 13.8002, tags)
+            .calculate_building_metrics(&index, &config, &mut Default::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn classifies_generic_buildings_by_use_without_mixed_use() {
+        let building = building_metrics(&[
+            ("building", "yes"),
+            ("building:use", "residential"),
+            ("building:levels", "3"),
+        ]);
+        assert_eq!(building.estimate.mixed_use, None);
+        assert_eq!(
+            building.estimate.classification.as_deref(),
+            Some("apartment")
+        );
+        assert_eq!(building.flats, 12);
+
+        let building = building_metrics(&[
+            ("building", "retail"),
+            ("building:use", "residential"),
+            ("building:levels", "3"),
+        ]);
+        assert_eq!(
+            building.estimate.mixed_use.as_deref(),
+            Some("building=retail, building:use=residential")
+        );
+        assert_eq!(building.flats, 8);
+    }
+
+    fn parts() -> Vec<GenericWay> {
+        vec![
+            rectangle(2, 13.7990, 13.7996, &[("building:levels", "4")]),
+            rectangle(3, 13.7996, 13.8002, &[("building:levels", "2")]),
+            // Centered outside of the outline
+            rectangle(4, 13.8000, 13.8010, &[("building:levels", "8")]),
+        ]
+    }
+
+    fn part_ids(part_relations: BTreeMap<OsmId, Vec<OsmId>>) -> Vec<OsmId> {
+        let index = SpatialIndex::new(vec![], vec![], parts(), part_relations, vec![], vec![]);
+        let building = way("yes");
+        let mut ids: Vec<OsmId> = index.parts_of(&building).map(|part| part.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn matches_parts_by_relation_or_centroid() {
+        let [west, east, outside] = [2, 3, 4].map(|id| OsmId::Way(WayId(id)));
+        assert_eq!(part_ids(BTreeMap::new()), [west, east]);
+
+        let relation = BTreeMap::from([(OsmId::Way(WayId(1)), vec![west, outside])]);
+        assert_eq!(part_ids(relation), [west, outside]);
+
+        // Relations of other buildings do not affect the centroid matching
+        let relation = BTreeMap::from([(OsmId::Way(WayId(9)), vec![outside])]);
+        assert_eq!(part_ids(relation), [west, east]);
+    }
+
+    #[test]
+    fn aggregates_part_storeys_by_part_area() {
+        let config = Config::builder().build();
+        let building = way("apartments");
+        let parts = parts();
+        let part_storeys = |parts: &[&GenericWay]| {
+            building
+                .calculate_part_storeys(parts, &config, &mut Default::default())
+                .unwrap()
+        };
+
+        assert_eq!(part_storeys(&[]), None);
+        // A single part covering half of the outline keeps its storeys
+        let storeys = part_storeys(&[&parts[0]]).unwrap();
+        assert!((storeys - 4.0).abs() < 1e-6, "{storeys}");
+        let storeys = part_storeys(&[&parts[0], &parts[1]]).unwrap();
+        assert!((storeys - 3.0).abs() < 1e-6, "{storeys}");
+        // Parts without height information count as a single storey
+        let unknown = rectangle(5, 13.7996, 13.8002, &[]);
+        let storeys = part_storeys(&[&parts[0], &unknown]).unwrap();
+        assert!((storeys - 2.5).abs() < 1e-6, "{storeys}");
+
+        let config = Config::builder()
+            .flat_estimator(FlatEstimator::FloorArea)
+            .build();
+        let (rule, _) = building.classification_rule(&config);
+        let estimate = building
+            .calculate_flat_estimate(
+                0,
+                rule,
+                &[&parts[0]],
+                None,
+                &config,
+                &mut Default::default(),
+            )
+            .unwrap();
+        assert_eq!(estimate.rule, FlatRule::FloorArea);
+        assert_eq!(estimate.parts, 1);
+        assert!((estimate.storeys.unwrap() - 4.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn awards_remainder_to_largest_quotas() {
+        assert_eq!(distribute(&[5, 3, 2], 1), [1, 0, 0]);
+        assert_eq!(distribute(&[2, 5, 0, 3], 2), [0, 1, 0, 1]);
+
+        let pop = distribute(&[1, 1, 0, 1, 1, 1], 3);
+        assert_eq!(pop.iter().sum::<u64>(), 3);
+        assert_eq!(pop[2], 0);
+        assert!(pop.iter().all(|pop| *pop <= 1));
+
+        assert_eq!(distribute(&[4, 0, 2], 0), [0, 0, 0]);
+    }
+
+    #[test]
+    fn estimates_only_classified_buildings_by_area() {
+        for flat_estimator in [FlatEstimator::Area, FlatEstimator::FloorArea] {
+            for building in ["garage", "industrial", "church"] {
+                let estimate = estimate_flats(building, flat_estimator);
+                assert_eq!(
+                    (estimate.rule, estimate.flats),
+                    (FlatRule::None, 0),
+                    "{building}"
+                );
+                assert_eq!(estimate.area, None);
+            }
+        }
+
+        let estimate = estimate_flats("yes", FlatEstimator::Area);
+        assert_eq!(estimate.rule, FlatRule::FootprintArea);
+        assert!(estimate.flats > 60, "{}", estimate.flats);
+        let estimate = estimate_flats("apartments", FlatEstimator::FloorArea);
+        assert_eq!(estimate.rule, FlatRule::FloorArea);
+    }
+}
@@ -0,0 +1,84 @@
+use osmpbfreader::OsmId;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::Write;
+
+use crate::config::Config;
+use crate::pbf::osm_type;
+use crate::Error;
+
+/// A malformed value found in the osm data and the fallback applied instead
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DataQualityIssue {
+    pub osm_type: String,
+    pub osm_id: i64,
+    pub tag: String,
+    pub value: String,
+    pub fallback: String,
+}
+
+impl DataQualityIssue {
+    fn new(object: OsmId, tag: &str, value: &str, fallback: &str) -> DataQualityIssue {
+        DataQualityIssue {
+            osm_type: osm_type(object).to_string(),
+            osm_id: object.inner_id(),
+            tag: tag.to_string(),
+            value: value.to_string(),
+            fallback: fallback.to_string(),
+        }
+    }
+}
+
+/// Collection of all data quality issues of a calculation
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DataQualityReport(pub(crate) Vec<DataQualityIssue>);
+
+impl DataQualityReport {
+    /// Records an issue in lenient mode, fails with an invalid tag error otherwise
+    pub(crate) fn record(
+        &mut self,
+        object: OsmId,
+        tag: &str,
+        value: &str,
+        fallback: &str,
+        config: &Config,
+    ) -> Result<(), Error> {
+        if !config.lenient {
+            return Err(Error::InvalidTag {
+                object,
+                key: tag.to_string(),
+                value: value.to_string(),
+            });
+        }
+        log::debug!("Invalid value {value:?} for {tag} on {object:?}, using {fallback}");
+        self.0
+            .push(DataQualityIssue::new(object, tag, value, fallback));
+        Ok(())
+    }
+
+    pub(crate) fn merge(&mut self, other: DataQualityReport) {
+        self.0.extend(other.0);
+    }
+
+    pub fn issues(&self) -> &[DataQualityIssue] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn write_json<W: Write>(&self, writer: W) -> Result<(), Error> {
+        serde_json::to_writer_pretty(writer, &self.0).map_err(Error::JsonError)
+    }
+
+    pub fn write_csv<W: Write>(&self, writer: W) -> Result<(), Error> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for issue in &self.0 {
+            writer.serialize(issue).map_err(Error::CsvError)?;
+        }
+        writer
+            .flush()
+            .map_err(|err| Error::CsvError(csv::Error::from(err)))
+    }
+}
@@ -0,0 +1,66 @@
+//! Runs the overpass input source against a local mock server
+
+use openhousepopulator::{calculate_buildings_overpass, Config, OverpassArea};
+use osmpbfreader::{OsmId, WayId};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+const REPLY: &str = r#"{"elements": [
+    {"type": "node", "id": 1, "lat": 47.9180, "lon": 13.7990},
+    {"type": "node", "id": 2, "lat": 47.9180, "lon": 13.8000},
+    {"type": "node", "id": 3, "lat": 47.9190, "lon": 13.8000},
+    {"type": "node", "id": 4, "lat": 47.9190, "lon": 13.7990},
+    {"type": "node", "id": 5, "lat": 47.9185, "lon": 13.7995, "tags": {"addr:housenumber": "1-5"}},
+    {"type": "way", "id": 10, "nodes": [1, 2, 3, 4, 1], "tags": {"building": "yes"}}
+]}"#;
+
+/// Answers a single http request with the canned reply, returning the received request body
+fn serve_once(listener: TcpListener) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{REPLY}",
+            REPLY.len()
+        )
+        .unwrap();
+        String::from_utf8(body).unwrap()
+    })
+}
+
+#[test]
+fn loads_buildings_from_configured_endpoint() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api/interpreter", listener.local_addr().unwrap());
+    let request = serve_once(listener);
+
+    let config = Config::builder().overpass_url(url).build();
+    let area: OverpassArea = "47.9,13.7,48,13.8".parse().unwrap();
+    let (buildings, _) = calculate_buildings_overpass(&area, false, &config).unwrap();
+
+    let building = buildings.get(OsmId::Way(WayId(10))).unwrap();
+    assert_eq!(building.estimate.house_numbers, 3);
+    assert_eq!(building.flats, 3);
+
+    let request = request.join().unwrap();
+    assert!(request.starts_with("data="));
+    assert!(request.contains("47.9%2C13.7%2C48%2C13.8"));
+}
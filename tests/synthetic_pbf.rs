@@ -0,0 +1,531 @@
+//! Regression tests running the whole pipeline on small pbf files built in memory
+
+use openhousepopulator::geometry::write_polygons_to_geojson;
+use openhousepopulator::{
+    calculate_buildings, Buildings, Config, DataQualityIssue, Error, FlatRule,
+};
+use osmpbfreader::fileformat::{Blob, BlobHeader};
+use osmpbfreader::osmformat::{
+    Node, PrimitiveBlock, PrimitiveGroup, Relation, Relation_MemberType, Way,
+};
+use osmpbfreader::{OsmId, OsmPbfReader, RelationId, WayId};
+use protobuf::Message;
+use std::io::Cursor;
+
+/// Builds a pbf with a single data block of plain nodes, ways and relations, tags are given as `(key, value)` pairs
+struct PbfBuilder {
+    strings: Vec<String>,
+    nodes: Vec<Node>,
+    ways: Vec<Way>,
+    relations: Vec<Relation>,
+}
+
+impl PbfBuilder {
+    fn new() -> PbfBuilder {
+        PbfBuilder {
+            // Index 0 of the string table is reserved
+            strings: vec![String::new()],
+            nodes: vec![],
+            ways: vec![],
+            relations: vec![],
+        }
+    }
+
+    fn string(&mut self, value: &str) -> u32 {
+        match self.strings.iter().position(|string| string == value) {
+            Some(index) => index as u32,
+            None => {
+                self.strings.push(value.to_string());
+                self.strings.len() as u32 - 1
+            }
+        }
+    }
+
+    fn tags(&mut self, tags: &[(&str, &str)]) -> (Vec<u32>, Vec<u32>) {
+        tags.iter()
+            .map(|(key, value)| (self.string(key), self.string(value)))
+            .unzip()
+    }
+
+    fn node(mut self, id: i64, lat: f64, lon: f64, tags: &[(&str, &str)]) -> PbfBuilder {
+        let (keys, vals) = self.tags(tags);
+        let mut node = Node::new();
+        node.set_id(id);
+        // With the default granularity of 100 nanodegrees
+        node.set_lat((lat * 10000000.).round() as i64);
+        node.set_lon((lon * 10000000.).round() as i64);
+        *node.mut_keys() = keys;
+        *node.mut_vals() = vals;
+        self.nodes.push(node);
+        self
+    }
+
+    fn way(mut self, id: i64, nodes: &[i64], tags: &[(&str, &str)]) -> PbfBuilder {
+        let (keys, vals) = self.tags(tags);
+        let mut way = Way::new();
+        way.set_id(id);
+        *way.mut_keys() = keys;
+        *way.mut_vals() = vals;
+        *way.mut_refs() = delta_coded(nodes.iter().copied());
+        self.ways.push(way);
+        self
+    }
+
+    /// Adds a relation of way members given as `(way id, role)`
+    fn relation(mut self, id: i64, members: &[(i64, &str)], tags: &[(&str, &str)]) -> PbfBuilder {
+        let (keys, vals) = self.tags(tags);
+        let mut relation = Relation::new();
+        relation.set_id(id);
+        *relation.mut_keys() = keys;
+        *relation.mut_vals() = vals;
+        *relation.mut_memids() = delta_coded(members.iter().map(|(way, _)| *way));
+        *relation.mut_roles_sid() = members
+            .iter()
+            .map(|(_, role)| self.string(role) as i32)
+            .collect();
+        *relation.mut_types() = vec![Relation_MemberType::WAY; members.len()];
+        self.relations.push(relation);
+        self
+    }
+
+    fn build(self) -> Vec<u8> {
+        let mut block = PrimitiveBlock::new();
+        for string in &self.strings {
+            block
+                .mut_stringtable()
+                .mut_s()
+                .push(string.as_bytes().to_vec());
+        }
+        let mut nodes = PrimitiveGroup::new();
+        nodes.mut_nodes().extend(self.nodes);
+        let mut ways = PrimitiveGroup::new();
+        ways.mut_ways().extend(self.ways);
+        let mut relations = PrimitiveGroup::new();
+        relations.mut_relations().extend(self.relations);
+        block.mut_primitivegroup().push(nodes);
+        block.mut_primitivegroup().push(ways);
+        block.mut_primitivegroup().push(relations);
+
+        let data = block.write_to_bytes().unwrap();
+        let mut blob = Blob::new();
+        blob.set_raw_size(data.len() as i32);
+        blob.set_raw(data);
+        let blob = blob.write_to_bytes().unwrap();
+        let mut header = BlobHeader::new();
+        header.set_field_type("OSMData".to_string());
+        header.set_datasize(blob.len() as i32);
+        let header = header.write_to_bytes().unwrap();
+
+        let mut pbf = (header.len() as u32).to_be_bytes().to_vec();
+        pbf.extend(header);
+        pbf.extend(blob);
+        pbf
+    }
+}
+
+/// Member ids are delta coded
+fn delta_coded(ids: impl Iterator<Item = i64>) -> Vec<i64> {
+    ids.scan(0, |previous, id| {
+        let delta = id - *previous;
+        *previous = id;
+        Some(delta)
+    })
+    .collect()
+}
+
+/// A building in Gmunden, whose latitude and longitude are far enough apart that swapped axes never match
+fn building(builder: PbfBuilder) -> PbfBuilder {
+    building_with(builder, &[("building", "yes")])
+}
+
+fn building_with(builder: PbfBuilder, tags: &[(&str, &str)]) -> PbfBuilder {
+    builder
+        .node(1, 47.9180, 13.7990, &[])
+        .node(2, 47.9180, 13.8000, &[])
+        .node(3, 47.9190, 13.8000, &[])
+        .node(4, 47.9190, 13.7990, &[])
+        .way(10, &[1, 2, 3, 4, 1], tags)
+}
+
+/// A row of apartment buildings with a house number node each
+fn street(mut builder: PbfBuilder, count: i64) -> PbfBuilder {
+    for index in 0..count {
+        let (lat, lon) = (47.9180, 13.7990 + index as f64 * 0.002);
+        let node = 100 + index * 5;
+        let housenumber = format!("{}-{}", index * 10 + 1, index * 10 + 1 + index * 2);
+        builder = builder
+            .node(node, lat, lon, &[])
+            .node(node + 1, lat, lon + 0.001, &[])
+            .node(node + 2, lat + 0.001, lon + 0.001, &[])
+            .node(node + 3, lat + 0.001, lon, &[])
+            .node(
+                node + 4,
+                lat + 0.0005,
+                lon + 0.0005,
+                &[("addr:housenumber", &housenumber)],
+            )
+            .way(
+                1000 + index,
+                &[node, node + 1, node + 2, node + 3, node],
+                &[("building", "apartments")],
+            );
+    }
+    builder
+}
+
+fn populations(buildings: &Buildings) -> Vec<(OsmId, u64)> {
+    buildings
+        .iter()
+        .map(|building| (building.id, building.pop))
+        .collect()
+}
+
+#[test]
+fn counts_address_nodes_inside_buildings() {
+    let pbf = building(PbfBuilder::new())
+        .node(5, 47.9185, 13.7995, &[("addr:housenumber", "1-9")])
+        .node(6, 47.9200, 13.7995, &[("addr:housenumber", "11")])
+        .build();
+
+    let config = Config::builder().build();
+    let (buildings, _) =
+        calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), false, &config).unwrap();
+
+    let building = buildings.get(OsmId::Way(WayId(10))).unwrap();
+    assert_eq!(building.estimate.house_numbers, 5);
+    assert_eq!(building.flats, 5);
+}
+
+#[test]
+fn ignores_address_nodes_at_swapped_coordinates() {
+    // The position of an address node inside the building with latitude and longitude swapped
+    let pbf = building(PbfBuilder::new())
+        .node(5, 13.7995, 47.9185, &[("addr:housenumber", "1")])
+        .build();
+
+    let config = Config::builder().build();
+    let (buildings, _) =
+        calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), false, &config).unwrap();
+
+    let house_numbers = buildings
+        .get(OsmId::Way(WayId(10)))
+        .map_or(0, |building| building.estimate.house_numbers);
+    assert_eq!(house_numbers, 0);
+}
+
+#[test]
+fn reports_malformed_flat_counts() {
+    let pbf = building_with(
+        PbfBuilder::new(),
+        &[("building", "apartments"), ("building:flats", "many")],
+    )
+    .build();
+
+    let config = Config::builder().build();
+    let (buildings, report) =
+        calculate_buildings(&mut OsmPbfReader::new(Cursor::new(&pbf)), false, &config).unwrap();
+    assert!(buildings.get(OsmId::Way(WayId(10))).is_some());
+    let issue = DataQualityIssue {
+        osm_type: "way".to_string(),
+        osm_id: 10,
+        tag: "building:flats".to_string(),
+        value: "many".to_string(),
+        fallback: "estimate".to_string(),
+    };
+    assert_eq!(report.issues(), std::slice::from_ref(&issue));
+
+    let mut json = vec![];
+    report.write_json(&mut json).unwrap();
+    let issues: Vec<DataQualityIssue> = serde_json::from_slice(&json).unwrap();
+    assert_eq!(issues, [issue]);
+
+    let mut csv = vec![];
+    report.write_csv(&mut csv).unwrap();
+    assert_eq!(
+        String::from_utf8(csv).unwrap(),
+        "osm_type,osm_id,tag,value,fallback\nway,10,building:flats,many,estimate\n"
+    );
+
+    let config = Config::builder().lenient(false).build();
+    let result = calculate_buildings(&mut OsmPbfReader::new(Cursor::new(&pbf)), false, &config);
+    assert!(matches!(
+        result,
+        Err(Error::InvalidTag { object: OsmId::Way(WayId(10)), key, value })
+            if key == "building:flats" && value == "many"
+    ));
+}
+
+#[test]
+fn writes_whitelisted_tags_and_explanations_to_geojson() {
+    let pbf = building_with(
+        PbfBuilder::new(),
+        &[
+            ("building", "apartments"),
+            ("building:flats", "6"),
+            ("addr:street", "Hauptstraße"),
+            ("name", "Seeblick"),
+        ],
+    )
+    .build();
+
+    let config = Config::builder().build();
+    let (buildings, _) =
+        calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), false, &config).unwrap();
+    let buildings = buildings.into_inner();
+
+    let geojson = serde_json::to_value(write_polygons_to_geojson(&buildings, false)).unwrap();
+    let feature = &geojson["features"][0];
+    assert_eq!(feature["id"], "way/10");
+    assert_eq!(feature["geometry"]["type"], "Polygon");
+    let properties = feature["properties"].as_object().unwrap();
+    assert_eq!(properties["osm_type"], "way");
+    assert_eq!(properties["osm_id"], 10);
+    assert_eq!(properties["building"], "apartments");
+    assert_eq!(properties["addr:street"], "Hauptstraße");
+    assert_eq!(properties["flats"], 6);
+    assert!(!properties.contains_key("name"));
+    assert!(!properties.contains_key("building:flats"));
+    assert!(!properties.contains_key("estimate"));
+
+    let geojson = serde_json::to_value(write_polygons_to_geojson(&buildings, true)).unwrap();
+    let estimate = &geojson["features"][0]["properties"]["estimate"];
+    assert_eq!(
+        *estimate,
+        serde_json::to_value(&buildings[0].estimate).unwrap()
+    );
+    assert_eq!(estimate["rule"], "explicit_flats");
+    assert_eq!(estimate["flats"], 6);
+}
+
+/// Flats mapped on the building, on an entrance on its outline and by unit nodes inside it
+fn mapped_flats(building_flats: Option<&str>, entrance_flats: Option<&str>) -> (FlatRule, usize) {
+    let mut tags = vec![("building", "apartments"), ("building:levels", "4")];
+    tags.extend(building_flats.map(|flats| ("building:flats", flats)));
+    let mut entrance = vec![("entrance", "main")];
+    entrance.extend(entrance_flats.map(|flats| ("addr:flats", flats)));
+    let pbf = PbfBuilder::new()
+        .node(1, 47.9180, 13.7990, &entrance)
+        .node(2, 47.9180, 13.8000, &[])
+        .node(3, 47.9190, 13.8000, &[])
+        .node(4, 47.9190, 13.7990, &[])
+        .way(10, &[1, 2, 3, 4, 1], &tags)
+        .node(5, 47.9184, 13.7994, &[("addr:unit", "A")])
+        .node(6, 47.9185, 13.7995, &[("addr:unit", "B")])
+        .node(7, 47.9186, 13.7996, &[("addr:unit", "A")])
+        .build();
+
+    let config = Config::builder().build();
+    let (buildings, _) =
+        calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), false, &config).unwrap();
+    let building = buildings.get(OsmId::Way(WayId(10))).unwrap();
+    assert_eq!(building.flats, building.estimate.flats);
+    (building.estimate.rule, building.flats)
+}
+
+#[test]
+fn prefers_building_flats_over_entrance_flats_over_units() {
+    assert_eq!(
+        mapped_flats(Some("20"), Some("1-12")),
+        (FlatRule::ExplicitFlats, 20)
+    );
+    assert_eq!(mapped_flats(None, Some("1-12")), (FlatRule::AddrFlats, 12));
+    assert_eq!(mapped_flats(None, None), (FlatRule::AddrUnits, 2));
+}
+
+#[test]
+fn excludes_buildings_only_by_landuse_nodes() {
+    let config = Config::builder().build();
+    for centroid in [false, true] {
+        let pbf = building(PbfBuilder::new())
+            .node(5, 47.9185, 13.7995, &[("leisure", "fitness_centre")])
+            .build();
+        let (buildings, _) =
+            calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), centroid, &config)
+                .unwrap();
+        assert!(buildings.get(OsmId::Way(WayId(10))).is_some());
+
+        let pbf = building(PbfBuilder::new())
+            .node(5, 47.9185, 13.7995, &[("landuse", "industrial")])
+            .build();
+        let (buildings, _) =
+            calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), centroid, &config)
+                .unwrap();
+        assert!(buildings.get(OsmId::Way(WayId(10))).is_none());
+    }
+}
+
+#[test]
+fn same_seed_gives_identical_population() {
+    let pbf = street(PbfBuilder::new(), 6).build();
+    let config = Config::builder().seed(Some(42)).build();
+    let load = || {
+        calculate_buildings(
+            &mut OsmPbfReader::new(Cursor::new(pbf.clone())),
+            false,
+            &config,
+        )
+        .unwrap()
+        .0
+    };
+
+    let runs: Vec<_> = (0..2)
+        .map(|_| {
+            let mut estimated = load();
+            estimated
+                .estimate_population(&config, &mut config.rng())
+                .unwrap();
+            let mut distributed = load();
+            distributed
+                .distribute_population(1000, &config, &mut config.rng())
+                .unwrap();
+            (populations(&estimated), populations(&distributed))
+        })
+        .collect();
+
+    assert_eq!(runs[0], runs[1]);
+    let (estimated, distributed) = &runs[0];
+    assert_eq!(estimated.len(), 6);
+    assert!(estimated.iter().all(|(_, pop)| *pop > 0));
+    assert_eq!(distributed.iter().map(|(_, pop)| pop).sum::<u64>(), 1000);
+}
+
+#[test]
+fn reduces_mixed_use_buildings_instead_of_excluding_them() {
+    let tags = [("building", "apartments"), ("building:levels", "4")];
+    // Even with amenities excluded, a building carrying one is only mixed use
+    let config = Config::builder()
+        .exclude_tags(vec!["amenity".to_string(), "leisure".to_string()])
+        .build();
+    let load = |pbf: Vec<u8>| {
+        calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), false, &config)
+            .unwrap()
+            .0
+    };
+
+    let buildings = load(building_with(PbfBuilder::new(), &tags).build());
+    let residential = buildings.get(OsmId::Way(WayId(10))).unwrap();
+    assert_eq!(residential.estimate.mixed_use, None);
+
+    let tagged = [tags[0], tags[1], ("amenity", "restaurant")];
+    let buildings = load(building_with(PbfBuilder::new(), &tagged).build());
+    let building = buildings.get(OsmId::Way(WayId(10))).unwrap();
+    assert_eq!(
+        building.estimate.mixed_use.as_deref(),
+        Some("amenity=restaurant")
+    );
+    assert!(building.flats < residential.flats);
+
+    let pbf = building_with(PbfBuilder::new(), &tags)
+        .node(5, 47.9185, 13.7995, &[("amenity", "pharmacy")])
+        .build();
+    let buildings = load(pbf);
+    let building = buildings.get(OsmId::Way(WayId(10))).unwrap();
+    assert_eq!(
+        building.estimate.mixed_use.as_deref(),
+        Some("amenity=pharmacy on node/5")
+    );
+    assert!(building.flats < residential.flats);
+}
+
+/// Square ring of four nodes around a center, `size` in degrees
+fn ring(
+    builder: PbfBuilder,
+    way: i64,
+    first_node: i64,
+    center: (f64, f64),
+    size: f64,
+    tags: &[(&str, &str)],
+) -> PbfBuilder {
+    let (lat, lon) = center;
+    let half = size / 2.;
+    builder
+        .node(first_node, lat - half, lon - half, &[])
+        .node(first_node + 1, lat - half, lon + half, &[])
+        .node(first_node + 2, lat + half, lon + half, &[])
+        .node(first_node + 3, lat + half, lon - half, &[])
+        .way(
+            way,
+            &[
+                first_node,
+                first_node + 1,
+                first_node + 2,
+                first_node + 3,
+                first_node,
+            ],
+            tags,
+        )
+}
+
+#[test]
+fn counts_address_nodes_of_building_relations_outside_their_courtyard() {
+    let center = (47.9185, 13.7995);
+    let pbf = ring(PbfBuilder::new(), 20, 1, center, 0.002, &[]);
+    let pbf = ring(pbf, 21, 5, center, 0.001, &[])
+        .relation(
+            30,
+            &[(20, "outer"), (21, "inner")],
+            &[("type", "multipolygon"), ("building", "yes")],
+        )
+        // In the courtyard
+        .node(40, center.0, center.1, &[("addr:housenumber", "1")])
+        // Between courtyard and outline
+        .node(
+            41,
+            center.0 + 0.0008,
+            center.1,
+            &[("addr:housenumber", "3;5")],
+        )
+        .build();
+
+    let config = Config::builder().build();
+    let (buildings, _) =
+        calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), false, &config).unwrap();
+
+    let building = buildings
+        .get(OsmId::Relation(RelationId(30)))
+        .expect("relation building is loaded");
+    assert_eq!(building.estimate.house_numbers, 2);
+    assert_eq!(building.flats, 2);
+    assert!(buildings.get(OsmId::Way(WayId(20))).is_none());
+}
+
+#[test]
+fn keeps_residential_enclaves_of_excluded_relations() {
+    let center = (47.9185, 13.7995);
+    let apartments = [("building", "apartments")];
+    let pbf = ring(PbfBuilder::new(), 20, 1, center, 0.01, &[]);
+    let pbf = ring(pbf, 21, 5, center, 0.004, &[]).relation(
+        30,
+        &[(20, "outer"), (21, "inner")],
+        &[("type", "multipolygon"), ("landuse", "industrial")],
+    );
+    // In the residential hole
+    let pbf = ring(pbf, 40, 10, center, 0.0005, &apartments);
+    // In the industrial area around it
+    let pbf = ring(
+        pbf,
+        41,
+        15,
+        (center.0 + 0.004, center.1),
+        0.0005,
+        &apartments,
+    );
+    // Outside of the relation
+    let pbf = ring(
+        pbf,
+        42,
+        20,
+        (center.0 + 0.01, center.1),
+        0.0005,
+        &apartments,
+    )
+    .build();
+
+    let config = Config::builder().build();
+    let (buildings, _) =
+        calculate_buildings(&mut OsmPbfReader::new(Cursor::new(pbf)), false, &config).unwrap();
+
+    assert!(buildings.get(OsmId::Way(WayId(40))).is_some());
+    assert!(buildings.get(OsmId::Way(WayId(41))).is_none());
+    assert!(buildings.get(OsmId::Way(WayId(42))).is_some());
+}